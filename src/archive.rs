@@ -0,0 +1,907 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+
+use crate::{
+    jobs::{Progress, ProgressReader},
+    ops::{unique_name, Conflict},
+    vfs::{DirEntry, Kind, MemFile, Stat, Vfs, VfsFile},
+};
+
+// Los miembros se descomprimen en memoria para leerlos; de uno enorme solo
+// nos interesa el principio para la preview.
+const MAX_MEMBER_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl Format {
+    pub fn of<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+        let format = if name.ends_with(".zip") {
+            Format::Zip
+        } else if name.ends_with(".tar") {
+            Format::Tar
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Format::TarGz
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Format::TarXz
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Format::TarZst
+        } else {
+            return None;
+        };
+
+        Some(format)
+    }
+}
+
+pub fn is_archive<P: AsRef<Path>>(path: P) -> bool {
+    Format::of(path).is_some()
+}
+
+/// Ruta de un miembro sin `./` ni `/` iniciales. Devuelve `None` si contiene
+/// `..`, porque al extraerla podria acabar fuera del directorio destino.
+pub fn normalize_member<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+
+    for c in path.as_ref().components() {
+        match c {
+            Component::Normal(c) => out.push(c),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(out)
+}
+
+/// Lista de miembros de un archivo, con los directorios intermedios que no
+/// aparecen explicitamente (muchos zip solo guardan los ficheros).
+#[derive(Debug, Default)]
+pub struct ArchiveIndex {
+    pub entries: HashMap<PathBuf, Stat>,
+    pub children: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Destino de los enlaces simbolicos (solo en tar).
+    pub links: HashMap<PathBuf, PathBuf>,
+}
+
+impl ArchiveIndex {
+    fn insert(&mut self, path: PathBuf, stat: Stat) {
+        let mut parent = path.parent();
+        while let Some(p) = parent {
+            if p.as_os_str().is_empty() || self.entries.contains_key(p) {
+                break;
+            }
+            self.entries.insert(
+                p.to_path_buf(),
+                Stat {
+                    kind: Kind::Dir,
+                    size: 0,
+                    modified: None,
+                    link: false,
+                },
+            );
+            parent = p.parent();
+        }
+
+        // Un tar puede repetir un miembro; vale el ultimo, como al extraer
+        self.entries.insert(path, stat);
+    }
+
+    fn link_children(&mut self) {
+        for path in self.entries.keys() {
+            let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            self.children.entry(parent).or_default().push(path.clone());
+        }
+    }
+
+    pub fn read(archive: &Path) -> io::Result<Self> {
+        let format = Format::of(archive)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an archive"))?;
+        let mut index = Self::default();
+
+        match format {
+            Format::Zip => {
+                let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))
+                    .map_err(io::Error::other)?;
+
+                for i in 0..zip.len() {
+                    let member = zip.by_index_raw(i).map_err(io::Error::other)?;
+                    let Some(path) = normalize_member(member.name()) else {
+                        continue;
+                    };
+                    if path.as_os_str().is_empty() {
+                        continue;
+                    }
+
+                    let modified = member
+                        .last_modified()
+                        .and_then(|t| NaiveDateTime::try_from(t).ok())
+                        .and_then(|t| Local.from_local_datetime(&t).single())
+                        .map(SystemTime::from);
+                    let kind = if member.is_dir() {
+                        Kind::Dir
+                    } else {
+                        Kind::File
+                    };
+
+                    index.insert(
+                        path,
+                        Stat {
+                            kind,
+                            size: member.size(),
+                            modified,
+                            link: member.is_symlink(),
+                        },
+                    );
+                }
+            }
+            _ => {
+                let mut tar = tar::Archive::new(tar_reader(archive, format)?);
+
+                for entry in tar.entries()? {
+                    let entry = entry?;
+                    let Some(path) = normalize_member(entry.path()?) else {
+                        continue;
+                    };
+                    if path.as_os_str().is_empty() {
+                        continue;
+                    }
+
+                    let header = entry.header();
+                    let kind = match header.entry_type().is_dir() {
+                        true => Kind::Dir,
+                        false => Kind::File,
+                    };
+                    let link = header.entry_type().is_symlink();
+                    if let Some(target) = entry.link_name()?.filter(|_| link) {
+                        index.links.insert(path.clone(), target.to_path_buf());
+                    }
+                    let modified = header
+                        .mtime()
+                        .ok()
+                        .map(|s| SystemTime::UNIX_EPOCH + Duration::from_secs(s));
+
+                    index.insert(
+                        path,
+                        Stat {
+                            kind,
+                            size: header.size().unwrap_or(0),
+                            modified,
+                            link,
+                        },
+                    );
+                }
+            }
+        }
+
+        index.link_children();
+        Ok(index)
+    }
+}
+
+/// Lector del tar descomprimido, sea cual sea la compresion.
+pub fn tar_reader(archive: &Path, format: Format) -> io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(archive)?);
+
+    let reader: Box<dyn Read> = match format {
+        Format::Tar => Box::new(file),
+        Format::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        Format::TarXz => Box::new(xz2::read::XzDecoder::new(file)),
+        Format::TarZst => Box::new(zstd::Decoder::with_buffer(file)?),
+        Format::Zip => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zip is not a tar archive",
+            ))
+        }
+    };
+
+    Ok(reader)
+}
+
+fn read_member(archive: &Path, inner: &Path) -> io::Result<Vec<u8>> {
+    let not_found = || io::Error::new(io::ErrorKind::NotFound, "member not found");
+    let format = Format::of(archive).ok_or_else(not_found)?;
+    let mut buf = Vec::new();
+
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))
+                .map_err(io::Error::other)?;
+
+            for i in 0..zip.len() {
+                let member = zip.by_index(i).map_err(io::Error::other)?;
+                if normalize_member(member.name()).as_deref() == Some(inner) {
+                    member.take(MAX_MEMBER_BYTES).read_to_end(&mut buf)?;
+                    return Ok(buf);
+                }
+            }
+        }
+        _ => {
+            let mut tar = tar::Archive::new(tar_reader(archive, format)?);
+
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if normalize_member(entry.path()?).as_deref() == Some(inner) {
+                    entry.take(MAX_MEMBER_BYTES).read_to_end(&mut buf)?;
+                    return Ok(buf);
+                }
+            }
+        }
+    }
+
+    Err(not_found())
+}
+
+/// Resultado de `extract`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExtractReport {
+    pub files: usize,
+    /// Ya existian (con `Conflict::Skip`) o eran enlaces, que no se extraen.
+    pub skipped: usize,
+    /// Miembros con `..` o que pasarian por un enlace simbolico; nunca se
+    /// escriben.
+    pub rejected: usize,
+}
+
+impl ExtractReport {
+    pub fn summary(&self) -> String {
+        let mut s = format!("{} files extracted", self.files);
+        if self.skipped > 0 {
+            s.push_str(&format!(", {} skipped", self.skipped));
+        }
+        if self.rejected > 0 {
+            s.push_str(&format!(", {} unsafe paths rejected", self.rejected));
+        }
+        s
+    }
+}
+
+/// Extrae todo `archive` dentro de `dest`. Los miembros que intentan salir de
+/// `dest` (con `..`, rutas absolutas ya normalizadas o atravesando un enlace
+/// simbolico que haya en el destino) se descartan.
+pub fn extract(
+    archive: &Path,
+    dest: &Path,
+    conflict: Conflict,
+    progress: &Progress,
+) -> io::Result<ExtractReport> {
+    let format = Format::of(archive)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an archive"))?;
+    let total = ArchiveFs::new(archive)
+        .index()?
+        .entries
+        .values()
+        .map(|s| s.size)
+        .sum();
+    progress.set_total(total);
+
+    fs::create_dir_all(dest)?;
+    let mut report = ExtractReport::default();
+
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))
+                .map_err(io::Error::other)?;
+
+            for i in 0..zip.len() {
+                let member = zip.by_index(i).map_err(io::Error::other)?;
+                let kind = match (member.is_dir(), member.is_symlink()) {
+                    (true, _) => Member::Dir,
+                    (_, true) => Member::Link,
+                    _ => Member::File(member.unix_mode()),
+                };
+                let name = member.name().to_string();
+                extract_member(dest, &name, kind, member, conflict, progress, &mut report)?;
+            }
+        }
+        _ => {
+            let mut tar = tar::Archive::new(tar_reader(archive, format)?);
+
+            for entry in tar.entries()? {
+                let entry = entry?;
+                let header = entry.header();
+                let kind = match header.entry_type() {
+                    t if t.is_dir() => Member::Dir,
+                    t if t.is_file() => Member::File(header.mode().ok()),
+                    _ => Member::Link,
+                };
+                let name = entry.path()?.to_path_buf();
+                extract_member(dest, &name, kind, entry, conflict, progress, &mut report)?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+enum Member {
+    Dir,
+    File(Option<u32>),
+    Link,
+}
+
+fn extract_member<R: Read>(
+    dest: &Path,
+    name: impl AsRef<Path>,
+    kind: Member,
+    reader: R,
+    conflict: Conflict,
+    progress: &Progress,
+    report: &mut ExtractReport,
+) -> io::Result<()> {
+    let Some(rel) = normalize_member(name) else {
+        report.rejected += 1;
+        return Ok(());
+    };
+    if rel.as_os_str().is_empty() {
+        return Ok(());
+    }
+    if crosses_symlink(dest, &rel) {
+        report.rejected += 1;
+        return Ok(());
+    }
+
+    let mut target = dest.join(&rel);
+    let mode = match kind {
+        Member::Dir => return fs::create_dir_all(&target),
+        Member::Link => {
+            report.skipped += 1;
+            return Ok(());
+        }
+        Member::File(mode) => mode,
+    };
+
+    if let Ok(existing) = target.symlink_metadata() {
+        match conflict {
+            Conflict::Skip => {
+                report.skipped += 1;
+                return Ok(());
+            }
+            Conflict::Rename => target = unique_name(&target),
+            // Nunca escribimos a traves de un enlace ni encima de un directorio
+            Conflict::Overwrite if existing.is_dir() => {
+                report.skipped += 1;
+                return Ok(());
+            }
+            Conflict::Overwrite => fs::remove_file(&target)?,
+        }
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = File::create(&target)?;
+    io::copy(&mut ProgressReader::new(reader, progress), &mut out)?;
+    if let Some(mode) = mode {
+        fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+
+    report.files += 1;
+    Ok(())
+}
+
+// `true` si algun directorio intermedio de `rel` ya existe en `dest` como
+// enlace simbolico: escribir ahi podria acabar fuera de `dest`.
+fn crosses_symlink(dest: &Path, rel: &Path) -> bool {
+    let mut path = dest.to_path_buf();
+    let parents = rel.parent().into_iter().flat_map(|p| p.components());
+
+    for c in parents {
+        path.push(c);
+        match path.symlink_metadata() {
+            Ok(m) if m.file_type().is_symlink() => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+
+    false
+}
+
+/// Nombre de la carpeta en la que extraer `archive`: `fotos.tar.gz` ->
+/// `fotos`.
+pub fn folder_name(archive: &Path) -> String {
+    let name = archive
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let lower = name.to_lowercase();
+
+    for ext in [
+        ".tar.gz", ".tar.xz", ".tar.zst", ".tgz", ".txz", ".tzst", ".tar", ".zip",
+    ] {
+        if lower.ends_with(ext) && lower.len() > ext.len() {
+            return name[..name.len() - ext.len()].to_string();
+        }
+    }
+    name
+}
+
+/// Crea `dest` con `sources` (ficheros o directorios, recursivamente). Dentro
+/// del archivo las rutas son relativas a `base`. El formato se deduce de la
+/// extension de `dest`. Devuelve el numero de ficheros guardados.
+pub fn create(
+    sources: &[PathBuf],
+    base: &Path,
+    dest: &Path,
+    progress: &Progress,
+) -> io::Result<usize> {
+    let format = Format::of(dest).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unknown archive format, use .zip .tar .tar.gz .tar.xz or .tar.zst",
+        )
+    })?;
+
+    let mut entries = Vec::new();
+    for source in sources {
+        walk(source, &mut entries)?;
+    }
+    // No meter el propio archivo si se esta creando dentro de la seleccion
+    entries.retain(|(p, _)| p != dest);
+    progress.set_total(
+        entries
+            .iter()
+            .filter(|(_, m)| m.is_file())
+            .map(|(_, m)| m.len())
+            .sum(),
+    );
+
+    let file = File::create(dest)?;
+    let result = match format {
+        Format::Zip => write_zip(file, &entries, base, progress),
+        Format::Tar => write_tar(file, &entries, base, progress).map(drop),
+        Format::TarGz => write_tar(
+            flate2::write::GzEncoder::new(file, flate2::Compression::default()),
+            &entries,
+            base,
+            progress,
+        )
+        .and_then(|w| w.finish().map(drop)),
+        Format::TarXz => write_tar(
+            xz2::write::XzEncoder::new(file, 6),
+            &entries,
+            base,
+            progress,
+        )
+        .and_then(|w| w.finish().map(drop)),
+        Format::TarZst => write_tar(zstd::Encoder::new(file, 0)?, &entries, base, progress)
+            .and_then(|w| w.finish().map(drop)),
+    };
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(dest);
+        return Err(e);
+    }
+    Ok(entries.iter().filter(|(_, m)| m.is_file()).count())
+}
+
+// Recorre `path` sin seguir enlaces simbolicos.
+fn walk(path: &Path, out: &mut Vec<(PathBuf, fs::Metadata)>) -> io::Result<()> {
+    let meta = path.symlink_metadata()?;
+    let is_dir = meta.is_dir();
+    out.push((path.to_owned(), meta));
+
+    if is_dir {
+        let mut children: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        children.sort();
+        for child in children {
+            walk(&child, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn relative(path: &Path, base: &Path) -> PathBuf {
+    path.strip_prefix(base)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| PathBuf::from(path.file_name().unwrap_or_default()))
+}
+
+fn write_tar<W: Write>(
+    w: W,
+    entries: &[(PathBuf, fs::Metadata)],
+    base: &Path,
+    progress: &Progress,
+) -> io::Result<W> {
+    let mut tar = tar::Builder::new(w);
+    tar.follow_symlinks(false);
+
+    for (path, meta) in entries {
+        let rel = relative(path, base);
+        if meta.is_file() {
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(meta);
+            let reader = ProgressReader::new(File::open(path)?, progress);
+            tar.append_data(&mut header, &rel, reader)?;
+        } else {
+            tar.append_path_with_name(path, &rel)?;
+        }
+    }
+
+    tar.into_inner()
+}
+
+fn write_zip(
+    w: File,
+    entries: &[(PathBuf, fs::Metadata)],
+    base: &Path,
+    progress: &Progress,
+) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(w);
+
+    for (path, meta) in entries {
+        let rel = relative(path, base).to_string_lossy().to_string();
+        let mut options = zip::write::SimpleFileOptions::default()
+            .unix_permissions(meta.permissions().mode() & 0o777);
+        let modified = meta
+            .modified()
+            .ok()
+            .map(|t| chrono::DateTime::<Local>::from(t).naive_local())
+            .and_then(|t| zip::DateTime::try_from(t).ok());
+        if let Some(modified) = modified {
+            options = options.last_modified_time(modified);
+        }
+
+        if meta.is_dir() {
+            zip.add_directory(rel, options).map_err(io::Error::other)?;
+        } else if meta.is_file() {
+            zip.start_file(rel, options).map_err(io::Error::other)?;
+            io::copy(
+                &mut ProgressReader::new(File::open(path)?, progress),
+                &mut zip,
+            )?;
+        }
+        // Los enlaces simbolicos no se guardan en zip
+    }
+
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+// Indices que se guardan como mucho; se tira el que lleva mas tiempo sin
+// usarse.
+const MAX_INDEXES: usize = 8;
+
+struct CachedIndex {
+    modified: Option<SystemTime>,
+    index: Arc<ArchiveIndex>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct IndexCache {
+    indexes: HashMap<PathBuf, CachedIndex>,
+    tick: u64,
+}
+
+impl IndexCache {
+    fn get(&mut self, archive: &Path, modified: Option<SystemTime>) -> Option<Arc<ArchiveIndex>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let cached = self.indexes.get_mut(archive)?;
+        if cached.modified != modified {
+            self.indexes.remove(archive);
+            return None;
+        }
+        cached.used = tick;
+        Some(cached.index.clone())
+    }
+
+    fn put(&mut self, archive: PathBuf, modified: Option<SystemTime>, index: Arc<ArchiveIndex>) {
+        self.tick += 1;
+        let used = self.tick;
+        self.indexes.insert(
+            archive,
+            CachedIndex {
+                modified,
+                index,
+                used,
+            },
+        );
+
+        if self.indexes.len() > MAX_INDEXES {
+            let oldest = self
+                .indexes
+                .iter()
+                .min_by_key(|(_, c)| c.used)
+                .map(|(p, _)| p.clone());
+            if let Some(oldest) = oldest {
+                self.indexes.remove(&oldest);
+            }
+        }
+    }
+}
+
+// Leer el indice de un tar.gz obliga a descomprimirlo entero, asi que se
+// guarda mientras el archivo no cambie.
+fn index_cache() -> &'static Mutex<IndexCache> {
+    static CACHE: OnceLock<Mutex<IndexCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+// El ultimo miembro leido; la preview lo vuelve a pedir cada vez que hace
+// scroll.
+type MemberCache = Option<(PathBuf, Arc<Vec<u8>>)>;
+
+fn member_cache() -> &'static Mutex<MemberCache> {
+    static CACHE: OnceLock<Mutex<MemberCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Vista de solo lectura del contenido de un archivo comprimido. Las rutas
+/// son las del archivo seguidas de la ruta del miembro:
+/// `/home/x/fotos.zip/2024/a.jpg`.
+pub struct ArchiveFs {
+    archive: PathBuf,
+}
+
+impl ArchiveFs {
+    pub fn new<P: AsRef<Path>>(archive: P) -> Self {
+        Self {
+            archive: archive.as_ref().to_owned(),
+        }
+    }
+
+    fn index(&self) -> io::Result<Arc<ArchiveIndex>> {
+        let mtime = std::fs::metadata(&self.archive)?.modified().ok();
+        let mut cache = index_cache().lock().unwrap();
+
+        if let Some(index) = cache.get(&self.archive, mtime) {
+            return Ok(index);
+        }
+
+        let index = Arc::new(ArchiveIndex::read(&self.archive)?);
+        cache.put(self.archive.clone(), mtime, index.clone());
+        Ok(index)
+    }
+
+    fn inner<'a>(&self, path: &'a Path) -> io::Result<&'a Path> {
+        path.strip_prefix(&self.archive)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "path outside archive"))
+    }
+}
+
+impl Vfs for ArchiveFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let inner = self.inner(path)?;
+        let index = self.index()?;
+
+        if !inner.as_os_str().is_empty() && !index.entries.get(inner).is_some_and(Stat::is_dir) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a directory"));
+        }
+
+        let children = index.children.get(inner).map_or(&[][..], |c| &c[..]);
+        Ok(children
+            .iter()
+            .map(|c| DirEntry::new(self.archive.join(c), index.entries[c].clone()))
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let inner = self.inner(path)?;
+        if inner.as_os_str().is_empty() {
+            let mut stat: Stat = std::fs::metadata(&self.archive)?.into();
+            stat.kind = Kind::Dir;
+            return Ok(stat);
+        }
+
+        self.index()?
+            .entries
+            .get(inner)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "member not found"))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.index()?
+            .links
+            .get(self.inner(path)?)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let mut cache = member_cache().lock().unwrap();
+        if let Some((cached, data)) = cache.as_ref() {
+            if cached == path {
+                return Ok(Box::new(MemFile(data.clone())));
+            }
+        }
+
+        let data = Arc::new(read_member(&self.archive, self.inner(path)?)?);
+        *cache = Some((path.to_owned(), data.clone()));
+        Ok(Box::new(MemFile(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::of("a.ZIP"), Some(Format::Zip));
+        assert_eq!(Format::of("/x/a.tar.gz"), Some(Format::TarGz));
+        assert_eq!(Format::of("a.tzst"), Some(Format::TarZst));
+        assert_eq!(Format::of("a.gz"), None);
+        assert_eq!(Format::of("tar"), None);
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_member("./a/b"), Some(PathBuf::from("a/b")));
+        assert_eq!(
+            normalize_member("/etc/passwd"),
+            Some(PathBuf::from("etc/passwd"))
+        );
+        assert_eq!(normalize_member("a/../../b"), None);
+    }
+
+    #[test]
+    fn index_cache_evicts() {
+        let mut cache = IndexCache::default();
+        let index = Arc::new(ArchiveIndex::default());
+        for i in 0..=MAX_INDEXES {
+            cache.put(PathBuf::from(i.to_string()), None, index.clone());
+            // El primero se sigue usando y no se tira
+            assert!(cache.get(Path::new("0"), None).is_some());
+        }
+        assert_eq!(cache.indexes.len(), MAX_INDEXES);
+        assert!(cache.get(Path::new("1"), None).is_none());
+        assert!(cache
+            .get(Path::new("0"), Some(SystemTime::UNIX_EPOCH))
+            .is_none());
+    }
+
+    #[test]
+    fn browse_tar_gz() {
+        let dir = std::env::temp_dir().join(format!("mag-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.tar.gz");
+
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::fast(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_mtime(1_700_000_000);
+        tar.append_data(&mut header, "src/main.rs", &b"hola\n"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap().flush().unwrap();
+
+        let fs = ArchiveFs::new(&path);
+        let root = fs.list(&path).unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].path, path.join("src"));
+        assert_eq!(root[0].kind, Kind::Dir);
+
+        let src = fs.list(&path.join("src")).unwrap();
+        assert_eq!(src[0].path, path.join("src/main.rs"));
+        assert_eq!(src[0].stat.as_ref().unwrap().size, 5);
+
+        let file = fs.open(&path.join("src/main.rs")).unwrap();
+        let mut buf = [0u8; 16];
+        let n = file.read_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf[..n], b"hola\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn create_and_extract() {
+        let dir = std::env::temp_dir().join(format!("mag-extract-{}", std::process::id()));
+        let src = dir.join("proyecto");
+        std::fs::create_dir_all(src.join("src")).unwrap();
+        std::fs::write(src.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(src.join("README"), "hola").unwrap();
+
+        for name in ["p.zip", "p.tar", "p.tar.gz", "p.tar.xz", "p.tar.zst"] {
+            let archive = dir.join(name);
+            let progress = Progress::default();
+            let n = create(std::slice::from_ref(&src), &dir, &archive, &progress).unwrap();
+            assert_eq!(n, 2);
+            assert_eq!(progress.status().percent(), 100);
+
+            let out = dir.join(format!("out-{}", name));
+            let report = extract(&archive, &out, Conflict::Skip, &Progress::default()).unwrap();
+            assert_eq!(report.files, 2, "{}", name);
+            assert_eq!(
+                std::fs::read_to_string(out.join("proyecto/src/main.rs")).unwrap(),
+                "fn main() {}"
+            );
+
+            // Otra vez: todo existe ya
+            let report = extract(&archive, &out, Conflict::Skip, &Progress::default()).unwrap();
+            assert_eq!((report.files, report.skipped), (0, 2));
+            let report = extract(&archive, &out, Conflict::Rename, &Progress::default()).unwrap();
+            assert_eq!(report.files, 2);
+            assert!(out.join("proyecto/README (1)").exists());
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn traversal() {
+        let dir = std::env::temp_dir().join(format!("mag-traversal-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("out")).unwrap();
+        let archive = dir.join("evil.tar");
+
+        // `tar::Builder` no deja escribir `..`, asi que montamos la cabecera a mano
+        let mut tar = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..13].copy_from_slice(b"../escaped.tx");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append(&header, &b"evil"[..]).unwrap();
+        tar.finish().unwrap();
+        drop(tar);
+
+        // Y un enlace en el destino que apunta fuera
+        std::os::unix::fs::symlink(&dir, dir.join("out/link")).unwrap();
+        let report = extract_member_test(&dir.join("out"), "link/x.txt");
+        assert_eq!(report.rejected, 1);
+
+        let report = extract(
+            &archive,
+            &dir.join("out"),
+            Conflict::Overwrite,
+            &Progress::default(),
+        )
+        .unwrap();
+        assert_eq!((report.files, report.rejected), (0, 1));
+        assert!(!dir.join("escaped.tx").exists());
+        assert!(!dir.join("x.txt").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn extract_member_test(dest: &Path, name: &str) -> ExtractReport {
+        let mut report = ExtractReport::default();
+        extract_member(
+            dest,
+            name,
+            Member::File(None),
+            &b"x"[..],
+            Conflict::Overwrite,
+            &Progress::default(),
+            &mut report,
+        )
+        .unwrap();
+        report
+    }
+
+    #[test]
+    fn folder_names() {
+        assert_eq!(folder_name(Path::new("/x/fotos.tar.gz")), "fotos");
+        assert_eq!(folder_name(Path::new("a.ZIP")), "a");
+        assert_eq!(folder_name(Path::new(".zip")), ".zip");
+    }
+}
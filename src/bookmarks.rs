@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::config;
+
+/// Marcas de una tecla, como en vim: `m<tecla>` guarda el directorio actual
+/// y `'<tecla>` vuelve a el.
+///
+/// Las del usuario se guardan en `$XDG_DATA_HOME/mag/bookmarks`, una por
+/// linea con la tecla, un espacio y la ruta. Ademas estan las de la config
+/// (`mark.<tecla> = ruta`), que no se pueden borrar desde mag.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    marks: BTreeMap<char, PathBuf>,
+    shared: BTreeMap<char, PathBuf>,
+    file: Option<PathBuf>,
+}
+
+/// Una marca tal y como se muestra en la lista.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    pub key: char,
+    pub path: PathBuf,
+    /// Viene de la config y no del fichero del usuario.
+    pub shared: bool,
+}
+
+impl Bookmarks {
+    pub fn load() -> Self {
+        let shared = config::get().marks.clone();
+        match config::data_dir() {
+            Some(dir) => Self::from_file(dir.join("bookmarks"), shared),
+            None => Self {
+                shared,
+                ..Self::default()
+            },
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P, shared: BTreeMap<char, PathBuf>) -> Self {
+        let mut marks = BTreeMap::new();
+        if let Ok(bytes) = fs::read(path.as_ref()) {
+            for line in bytes.split(|b| *b == b'\n') {
+                // La tecla siempre es UTF-8; la ruta puede no serlo
+                let Some(space) = line.iter().position(|b| *b == b' ') else {
+                    continue;
+                };
+                let key = std::str::from_utf8(&line[..space]).ok();
+                let mut chars = key.unwrap_or_default().chars();
+                if let (Some(key), None) = (chars.next(), chars.next()) {
+                    let rest = &line[space + 1..];
+                    marks.insert(key, PathBuf::from(OsStr::from_bytes(rest)));
+                }
+            }
+        }
+
+        Self {
+            marks,
+            shared,
+            file: Some(path.as_ref().to_owned()),
+        }
+    }
+
+    pub fn get(&self, key: char) -> Option<&PathBuf> {
+        self.marks.get(&key).or_else(|| self.shared.get(&key))
+    }
+
+    pub fn set<P: AsRef<Path>>(&mut self, key: char, path: P) {
+        self.marks.insert(key, path.as_ref().to_owned());
+        self.save();
+    }
+
+    /// Borra la marca del usuario. Devuelve `false` si no habia, p.ej.
+    /// porque solo esta definida en la config.
+    pub fn remove(&mut self, key: char) -> bool {
+        let removed = self.marks.remove(&key).is_some();
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    /// Todas las marcas por orden de tecla. Si una tecla esta en los dos
+    /// sitios solo sale la del usuario.
+    pub fn list(&self) -> Vec<Mark> {
+        let mut all: BTreeMap<char, Mark> = BTreeMap::new();
+        for (shared, marks) in [(true, &self.shared), (false, &self.marks)] {
+            for (key, path) in marks {
+                let mark = Mark {
+                    key: *key,
+                    path: path.clone(),
+                    shared,
+                };
+                all.insert(*key, mark);
+            }
+        }
+        all.into_values().collect()
+    }
+
+    // Si no se puede escribir simplemente no se guarda
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let mut out = Vec::new();
+        for (key, path) in &self.marks {
+            let bytes = path.as_os_str().as_bytes();
+            if !bytes.contains(&b'\n') {
+                out.extend_from_slice(format!("{} ", key).as_bytes());
+                out.extend_from_slice(bytes);
+                out.push(b'\n');
+            }
+        }
+
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks() {
+        let dir = std::env::temp_dir().join(format!("mag-bookmarks-{}", std::process::id()));
+        let file = dir.join("bookmarks");
+        let shared = BTreeMap::from([('p', PathBuf::from("/srv")), ('t', PathBuf::from("/tmp"))]);
+
+        let mut b = Bookmarks::from_file(&file, shared.clone());
+        b.set('a', "/home/a b");
+        b.set('ñ', "/ñu");
+        b.set('t', "/var/tmp");
+        assert_eq!(b.get('t'), Some(&PathBuf::from("/var/tmp")));
+        assert_eq!(b.get('p'), Some(&PathBuf::from("/srv")));
+        assert!(!b.remove('p'));
+
+        let b = Bookmarks::from_file(&file, shared);
+        let keys: Vec<(char, bool)> = b.list().iter().map(|m| (m.key, m.shared)).collect();
+        assert_eq!(
+            keys,
+            [('a', false), ('p', true), ('t', false), ('ñ', false)]
+        );
+        assert_eq!(b.get('a'), Some(&PathBuf::from("/home/a b")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    env,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::text;
+
+const SEPARATOR: &str = "/";
+const ELLIPSIS: &str = "…";
+/// Teclas para saltar a cada trozo, de la raiz hacia abajo.
+pub const HINTS: &str = "1234567890abcdefghijklmnopqrstuvwxyz";
+
+/// Un trozo de la barra de ruta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub label: String,
+    /// Columna donde empieza, contando desde el principio de la barra.
+    pub x: usize,
+    /// El directorio al que lleva o, en el `…` de en medio, los que se han
+    /// quitado para que quepa.
+    pub dirs: Vec<PathBuf>,
+    /// Si detras va un separador.
+    pub sep: bool,
+}
+
+impl Segment {
+    pub fn end(&self) -> usize {
+        self.x + text::width(&self.label)
+    }
+}
+
+/// Barra de ruta de `path` en como mucho `width` columnas, con `~` en lugar
+/// del directorio personal.
+pub fn segments(path: &Path, width: usize) -> Vec<Segment> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    layout(path, home.as_deref(), width)
+}
+
+/// El trozo que hay en la columna `x` de la barra.
+pub fn at(segments: &[Segment], x: usize) -> Option<&Segment> {
+    segments.iter().find(|s| (s.x..s.end()).contains(&x))
+}
+
+/// La tecla para saltar al trozo `i`.
+pub fn hint(i: usize) -> Option<char> {
+    HINTS.chars().nth(i)
+}
+
+fn layout(path: &Path, home: Option<&Path>, width: usize) -> Vec<Segment> {
+    let crumbs = crumbs(path, home);
+
+    // Se van quitando los de despues del primero hasta que quepa; el ultimo
+    // se queda siempre
+    let mut hidden = 0;
+    let mut segments = place(&crumbs, hidden);
+    while end(&segments) > width && hidden + 2 < crumbs.len() {
+        hidden += 1;
+        segments = place(&crumbs, hidden);
+    }
+
+    // Si ni asi cabe se corta el ultimo
+    segments.retain(|s| s.x < width);
+    if let Some(last) = segments.last_mut() {
+        last.label = text::clip(&last.label, width - last.x);
+        last.sep = false;
+    }
+    segments
+}
+
+fn end(segments: &[Segment]) -> usize {
+    segments.last().map_or(0, Segment::end)
+}
+
+// Cada directorio de `path` con su nombre
+fn crumbs(path: &Path, home: Option<&Path>) -> Vec<(String, PathBuf)> {
+    let mut crumbs = Vec::new();
+    let mut dir = PathBuf::new();
+    let mut rest = path;
+
+    if let Some(home) = home.filter(|h| h.parent().is_some()) {
+        if let Ok(inside) = path.strip_prefix(home) {
+            crumbs.push(("~".to_string(), home.to_owned()));
+            dir = home.to_owned();
+            rest = inside;
+        }
+    }
+    for component in rest.components() {
+        dir.push(component);
+        let label = match component {
+            Component::RootDir => SEPARATOR.to_string(),
+            c => text::sanitize_os_str(c.as_os_str()),
+        };
+        crumbs.push((label, dir.clone()));
+    }
+    crumbs
+}
+
+// Coloca los trozos quitando `hidden` detras del primero
+fn place(crumbs: &[(String, PathBuf)], hidden: usize) -> Vec<Segment> {
+    let mut labels: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    for (i, (label, dir)) in crumbs.iter().enumerate() {
+        match i {
+            1 if hidden > 0 => {
+                let dirs = crumbs[1..=hidden].iter().map(|(_, d)| d.clone()).collect();
+                labels.push((ELLIPSIS.to_string(), dirs));
+            }
+            i if i > 1 && i <= hidden => {}
+            _ => labels.push((label.clone(), vec![dir.clone()])),
+        }
+    }
+
+    let mut x = 0;
+    let count = labels.len();
+    let mut segments = Vec::new();
+    for (i, (label, dirs)) in labels.into_iter().enumerate() {
+        // La raiz ya es un separador
+        let sep = i + 1 < count && label != SEPARATOR;
+        let segment = Segment {
+            x,
+            label,
+            dirs,
+            sep,
+        };
+        x = segment.end() + if sep { SEPARATOR.len() } else { 0 };
+        segments.push(segment);
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(segments: &[Segment]) -> String {
+        let mut s = String::new();
+        for seg in segments {
+            s.push_str(&seg.label);
+            if seg.sep {
+                s.push_str(SEPARATOR);
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn layout_segments() {
+        let home = Some(Path::new("/home/ana"));
+
+        let s = layout(Path::new("/srv/www/site"), home, 80);
+        assert_eq!(bar(&s), "/srv/www/site");
+        assert_eq!(s[2].x, 5);
+        assert_eq!(s[2].dirs, [PathBuf::from("/srv/www")]);
+        assert_eq!(at(&s, 6).unwrap().label, "www");
+        assert!(at(&s, 8).is_none());
+
+        let s = layout(Path::new("/home/ana/docs/mag"), home, 80);
+        assert_eq!(bar(&s), "~/docs/mag");
+        assert_eq!(s[0].dirs, [PathBuf::from("/home/ana")]);
+        assert_eq!(bar(&layout(Path::new("/"), home, 80)), "/");
+
+        let s = layout(Path::new("/home/ana/a/bbbb/cccc/dd"), home, 12);
+        assert_eq!(bar(&s), "~/…/cccc/dd");
+        assert_eq!(
+            s[1].dirs,
+            [
+                PathBuf::from("/home/ana/a"),
+                PathBuf::from("/home/ana/a/bbbb")
+            ]
+        );
+
+        let s = layout(Path::new("/verylongname"), home, 6);
+        assert_eq!(bar(&s), "/veryl");
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::{filesys::MagFolder, vfs};
+
+// Directorios que se guardan como mucho; se tira el que lleva mas tiempo
+// sin usarse.
+const MAX_DIRS: usize = 64;
+
+struct Cached {
+    folder: MagFolder,
+    modified: SystemTime,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    dirs: HashMap<PathBuf, Cached>,
+    tick: u64,
+    // Nombre de la ultima entrada seleccionada en cada directorio visitado.
+    // No se borra con los listados: es poco y sigue valiendo aunque el
+    // directorio cambie.
+    cursors: HashMap<PathBuf, OsString>,
+}
+
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Guarda un listado ya completo. La fecha de modificacion es la del
+/// directorio cuando se empezo a leer.
+///
+/// Editar un archivo no cambia la fecha del directorio, asi que el `stat` de
+/// cada entrada no se guarda: se vuelve a leer al usar el listado.
+pub fn put(mut folder: MagFolder) {
+    let Some(modified) = folder.data.metadata().and_then(|m| m.modified) else {
+        return;
+    };
+    if folder.error.is_some() {
+        return;
+    }
+
+    for entry in &mut folder.items {
+        entry.forget_metadata();
+    }
+
+    let mut cache = cache().lock().unwrap();
+    cache.tick += 1;
+    let used = cache.tick;
+    cache.dirs.insert(
+        folder.data.path.clone(),
+        Cached {
+            folder,
+            modified,
+            used,
+        },
+    );
+
+    if cache.dirs.len() > MAX_DIRS {
+        let oldest = cache
+            .dirs
+            .iter()
+            .min_by_key(|(_, c)| c.used)
+            .map(|(p, _)| p.clone());
+        if let Some(oldest) = oldest {
+            cache.dirs.remove(&oldest);
+        }
+    }
+}
+
+/// El listado de `path`, si esta guardado y el directorio no ha cambiado
+/// desde entonces.
+pub fn get(path: &Path) -> Option<MagFolder> {
+    let modified = vfs::stat(path).ok().and_then(|s| s.modified);
+
+    let mut cache = cache().lock().unwrap();
+    cache.tick += 1;
+    let tick = cache.tick;
+    let cached = cache.dirs.get_mut(path)?;
+    if Some(cached.modified) != modified {
+        cache.dirs.remove(path);
+        return None;
+    }
+
+    cached.used = tick;
+    Some(cached.folder.clone())
+}
+
+/// Olvida `path`, p.ej. porque inotify ha avisado de un cambio.
+pub fn invalidate(path: &Path) {
+    cache().lock().unwrap().dirs.remove(path);
+}
+
+/// Apunta que en `dir` el cursor estaba sobre la entrada `name`.
+pub fn remember_cursor(dir: &Path, name: &OsStr) {
+    let mut cache = cache().lock().unwrap();
+    cache.cursors.insert(dir.to_owned(), name.to_owned());
+}
+
+/// La ruta de la entrada que estaba seleccionada al salir de `dir`.
+pub fn cursor(dir: &Path) -> Option<PathBuf> {
+    let cache = cache().lock().unwrap();
+    cache.cursors.get(dir).map(|name| dir.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs::{self, File},
+        time::Duration,
+    };
+
+    #[test]
+    fn put_get_invalidate() {
+        let dir = std::env::temp_dir().join(format!("mag-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), "").unwrap();
+
+        let folder = MagFolder::new(&dir).get_entries_return().unwrap();
+        put(folder);
+        assert_eq!(get(&dir).unwrap().items.len(), 1);
+
+        invalidate(&dir);
+        assert!(get(&dir).is_none());
+
+        // Los datos de las entradas se leen de nuevo: cambiar un archivo no
+        // cambia la fecha del directorio
+        let folder = MagFolder::new(&dir).get_entries_return().unwrap();
+        put(folder);
+        fs::write(dir.join("a"), "hola").unwrap();
+        let cached = get(&dir).unwrap();
+        assert_eq!(cached.items[0].metadata().unwrap().size, 4);
+
+        // Si el directorio cambia el listado guardado ya no vale
+        let modified = fs::metadata(&dir).unwrap().modified().unwrap();
+        File::open(&dir)
+            .unwrap()
+            .set_modified(modified - Duration::from_secs(60))
+            .unwrap();
+        let folder = MagFolder::new(&dir).get_entries_return().unwrap();
+        put(folder);
+        assert!(get(&dir).is_some());
+        File::open(&dir).unwrap().set_modified(modified).unwrap();
+        assert!(get(&dir).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cursors() {
+        let dir = Path::new("/mag-cursor-test");
+        assert!(cursor(dir).is_none());
+        remember_cursor(dir, OsStr::new("b"));
+        remember_cursor(dir, OsStr::new("c"));
+        assert_eq!(cursor(dir), Some(dir.join("c")));
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    env,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::vfs;
+
+/// Convierte lo que escribe el usuario en una ruta absoluta: `~` es $HOME y
+/// las rutas relativas lo son a `base`. Los `.` y `..` se resuelven sin mirar
+/// el disco, como hace la shell con `cd`.
+pub fn expand(input: &str, base: &Path) -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let path = match (input.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => base.join(input),
+    };
+    normalize(&path)
+}
+
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Las formas de completar `input`, tal y como las escribiria el usuario
+/// (conservando `~` y lo relativo). Los directorios llevan `/` al final y
+/// los ocultos solo salen si se ha empezado a escribir el punto.
+pub fn complete_path(input: &str, base: &Path) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None if input == "~" => return vec!["~/".to_string()],
+        None => ("", input),
+    };
+    let listed = match dir {
+        "" => base.to_owned(),
+        d => expand(d, base),
+    };
+
+    let Ok(entries) = vfs::list(&listed) else {
+        return Vec::new();
+    };
+    let mut found: Vec<String> = entries
+        .into_iter()
+        .filter_map(|e| {
+            // Lo que no es UTF-8 no se puede escribir en el prompt
+            let name = e.path.file_name()?.to_str()?.to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if e.kind == vfs::Kind::Dir { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn expand_paths() {
+        let base = Path::new("/srv/www");
+        assert_eq!(expand("logs", base), PathBuf::from("/srv/www/logs"));
+        assert_eq!(expand("../x/./y", base), PathBuf::from("/srv/x/y"));
+        assert_eq!(expand("/etc", base), PathBuf::from("/etc"));
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(expand("~", base), PathBuf::from(&home));
+            assert_eq!(expand("~/a", base), PathBuf::from(home).join("a"));
+        }
+    }
+
+    #[test]
+    fn completion() {
+        let dir = std::env::temp_dir().join(format!("mag-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("dos.txt"), "").unwrap();
+        fs::write(dir.join("uno.txt"), "").unwrap();
+
+        assert_eq!(complete_path("d", &dir), ["docs/", "dos.txt"]);
+        assert_eq!(complete_path("", &dir), ["docs/", "dos.txt", "uno.txt"]);
+        assert_eq!(complete_path(".g", &dir), [".git/"]);
+
+        let abs = format!("{}/u", dir.display());
+        assert_eq!(
+            complete_path(&abs, Path::new("/")),
+            [format!("{}/uno.txt", dir.display())]
+        );
+        assert_eq!(complete_path("docs/", &dir), Vec::<String>::new());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
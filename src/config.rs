@@ -0,0 +1,218 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+const DEFAULT_TAB_WIDTH: usize = 4;
+const DEFAULT_FLATTEN_DEPTH: usize = 4;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Opciones leidas de `$XDG_CONFIG_HOME/mag/config`.
+///
+/// El fichero es una lista de lineas `clave = valor`; las lineas vacias y las
+/// que empiezan por `#` se ignoran, igual que las claves desconocidas.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub tab_width: usize,
+    /// Mostrar resumen, tamaños y fechas al previsualizar un directorio.
+    pub dir_details: bool,
+    /// Marcas compartidas, `mark.<tecla> = ruta`. Las que el usuario guarde
+    /// con `m` tienen prioridad.
+    pub marks: BTreeMap<char, PathBuf>,
+    /// Base de datos de z o zoxide con la que empezar la de mag.
+    pub import_z: Option<PathBuf>,
+    /// Disposicion de las ventanas al arrancar: `miller` o `commander`.
+    pub layout: String,
+    /// Mostrar al arrancar los ficheros que empiezan por punto.
+    pub show_hidden: bool,
+    /// Profundidad inicial de la lista aplanada.
+    pub flatten_depth: usize,
+    /// Columnas de directorios padre a la izquierda.
+    pub parent_columns: usize,
+    /// Proporciones de cada columna de padres, la del medio y la preview,
+    /// `column_ratios = 1:2:2`.
+    pub column_ratios: [u32; 3],
+    /// Mostrar la columna de preview.
+    pub preview: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: DEFAULT_TAB_WIDTH,
+            dir_details: false,
+            marks: BTreeMap::new(),
+            import_z: None,
+            layout: "miller".to_string(),
+            show_hidden: true,
+            flatten_depth: DEFAULT_FLATTEN_DEPTH,
+            parent_columns: 1,
+            column_ratios: [1, 2, 2],
+            preview: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        match config_dir() {
+            Some(dir) => Self::from_file(dir.join("config")),
+            None => Self::default(),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
+        match fs::read_to_string(path) {
+            Ok(s) => Self::parse(&s),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut config = Self::default();
+
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "tab_width" => {
+                    if let Ok(n) = value.parse::<usize>() {
+                        config.tab_width = n.clamp(1, 16);
+                    }
+                }
+                "dir_details" => {
+                    if let Some(b) = parse_bool(value) {
+                        config.dir_details = b;
+                    }
+                }
+                "show_hidden" => {
+                    if let Some(b) = parse_bool(value) {
+                        config.show_hidden = b;
+                    }
+                }
+                "flatten_depth" => {
+                    if let Ok(n) = value.parse::<usize>() {
+                        config.flatten_depth = n.clamp(1, 32);
+                    }
+                }
+                "parent_columns" => {
+                    if let Ok(n) = value.parse::<usize>() {
+                        config.parent_columns = n;
+                    }
+                }
+                "column_ratios" => {
+                    if let Some(ratios) = parse_ratios(value) {
+                        config.column_ratios = ratios;
+                    }
+                }
+                "preview" => {
+                    if let Some(b) = parse_bool(value) {
+                        config.preview = b;
+                    }
+                }
+                "import_z" => config.import_z = Some(expand_home(value)),
+                "layout" => config.layout = value.to_string(),
+                _ => {
+                    if let Some(key) = key.strip_prefix("mark.") {
+                        let mut chars = key.chars();
+                        if let (Some(c), None) = (chars.next(), chars.next()) {
+                            config.marks.insert(c, expand_home(value));
+                        }
+                    }
+                }
+            }
+        }
+
+        config
+    }
+}
+
+// `~/algo` es relativo a $HOME, para que la misma config valga a todos
+fn expand_home(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+fn parse_ratios(value: &str) -> Option<[u32; 3]> {
+    let ratios: Vec<u32> = value
+        .split(':')
+        .map(|r| r.trim().parse().ok().filter(|r| (1..=9).contains(r)))
+        .collect::<Option<_>>()?;
+    ratios.try_into().ok()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Configuracion global. Si `init` no se ha llamado se usan los valores por
+/// defecto, lo que permite usar los modulos de UI desde los tests.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Donde se guardan los datos que mag va generando (historial...).
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("mag")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback).join("mag")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let c = Config::parse("# comentario\n\ntab_width = 2\nunknown = 3\ndir_details = yes\n");
+        assert_eq!(c.tab_width, 2);
+        assert!(c.dir_details);
+
+        let c = Config::parse("tab_width = nope\nlayout = commander\nshow_hidden = no");
+        assert_eq!(c.tab_width, DEFAULT_TAB_WIDTH);
+        assert_eq!(c.layout, "commander");
+        assert!(!c.show_hidden);
+
+        let c = Config::parse("column_ratios = 1:3:4\npreview = off");
+        assert_eq!(c.column_ratios, [1, 3, 4]);
+        assert!(!c.preview);
+        let c = Config::parse("column_ratios = 1:3\nparent_columns = 2");
+        assert_eq!(c.column_ratios, [1, 2, 2]);
+        assert_eq!(c.parent_columns, 2);
+
+        let c = Config::parse("mark.p = /srv/proyectos\nmark.xx = /nope\nmark.h = ~/docs");
+        assert_eq!(c.marks.len(), 2);
+        assert_eq!(c.marks[&'p'], PathBuf::from("/srv/proyectos"));
+        assert!(c.marks[&'h'].ends_with("docs"));
+    }
+}
@@ -1,37 +1,77 @@
 use std::{
-    fs::{self, File, Metadata},
-    io::Read,
-    os::unix::fs::FileExt,
+    cell::OnceCell,
+    io::ErrorKind,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
-const FILE_READ_BYTES: usize = 250;
+use crate::{
+    archive,
+    pos::Pos,
+    text,
+    vfs::{self, Stat},
+};
+
+const READ_CHUNK: usize = 8192;
+const HEX_BYTES_PER_LINE: usize = 16;
+// Una linea mas larga se parte en varias para no cargar un fichero minificado
+// entero en memoria.
+const MAX_LINE_BYTES: usize = 4096;
+const MAX_PREVIEW_LINES: usize = 100_000;
+// Filas de la ventana de preview que no se usan para el contenido.
+const PREVIEW_MARGIN: i32 = 3;
+// Con mas entradas el resumen no suma tamaños ni busca la mas reciente:
+// haria falta un `stat` de cada una.
+const SUMMARY_MAX_STAT: usize = 5000;
 
 #[derive(Debug, Clone)]
 pub struct MagItem {
     pub path: PathBuf,
-    pub metadata: Option<Metadata>,
+    // Se rellena la primera vez que se pide: en un directorio enorme solo se
+    // hace `stat` de las filas que se llegan a ver.
+    metadata: OnceCell<Option<Stat>>,
 }
 
 impl MagItem {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         let path = path.as_ref().to_owned();
-        let metadata = match fs::metadata(&path) {
-            Ok(meta) => Some(meta),
-            Err(_) => None,
-        };
+        let metadata = OnceCell::from(vfs::stat(&path).ok());
         Self { path, metadata }
     }
 
-    pub fn from<P: AsRef<Path>>(path: P, metadata: Option<Metadata>) -> Self {
+    /// Con `metadata` a `None` se leera cuando haga falta.
+    pub fn from<P: AsRef<Path>>(path: P, metadata: Option<Stat>) -> Self {
         let path = path.as_ref().to_owned();
+        let metadata = match metadata {
+            Some(m) => OnceCell::from(Some(m)),
+            None => OnceCell::new(),
+        };
 
         Self { path, metadata }
     }
 
+    pub fn metadata(&self) -> Option<&Stat> {
+        self.metadata
+            .get_or_init(|| vfs::stat(&self.path).ok())
+            .as_ref()
+    }
+
+    /// Olvida el `stat` leido, para que se vuelva a leer la proxima vez.
+    pub fn forget_metadata(&mut self) {
+        self.metadata = OnceCell::new();
+    }
+
+    /// A donde apunta, si es un enlace simbolico.
+    pub fn link_target(&self) -> Option<PathBuf> {
+        match self.metadata() {
+            Some(m) if m.link => vfs::read_link(&self.path).ok(),
+            _ => None,
+        }
+    }
+
     pub fn file_name(&self) -> String {
         match self.path.file_name() {
-            Some(s) => s.to_string_lossy().to_string(),
+            Some(s) => text::sanitize_os_str(s),
             None => String::from("CORRUPTED FILE NAME"),
         }
     }
@@ -40,85 +80,283 @@ impl MagItem {
 #[derive(Debug, Clone)]
 pub struct MagFile {
     pub data: MagItem,
-    pub content: String,
+    /// Lineas ya leidas del fichero (o del volcado hexadecimal si es binario).
+    pub lines: Vec<String>,
+    pub binary: bool,
+    /// Primera linea visible en la ventana de preview.
+    pub scroll: usize,
+    /// Bytes del fichero consumidos hasta ahora.
+    pub offset: u64,
+    pub eof: bool,
+    // Trozo de linea leido que todavia no ha llegado a su '\n'.
+    partial: Vec<u8>,
 }
 
 impl MagFile {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        let path = path.as_ref().to_owned();
-        let data = MagItem::new(&path);
-        let content = String::with_capacity(FILE_READ_BYTES);
+        Self {
+            data: MagItem::new(path),
+            ..Self::new_empty()
+        }
+    }
 
-        Self { data, content }
+    fn new_empty() -> Self {
+        Self {
+            data: MagItem::from(PathBuf::new(), None),
+            lines: Vec::new(),
+            binary: false,
+            scroll: 0,
+            offset: 0,
+            eof: false,
+            partial: Vec::new(),
+        }
     }
 
-    pub fn new_return<P: AsRef<Path>>(path: P) -> Self {
-        let path = path.as_ref().to_owned();
-        let data = MagItem::new(&path);
-        let mut content = String::with_capacity(FILE_READ_BYTES + 1);
-        let mut buf = [0u8; FILE_READ_BYTES];
+    pub fn new_return<P: AsRef<Path>>(path: P, dim: Pos<i32>) -> Self {
+        let mut file = Self::new(path);
+        file.file_contents(dim);
+        file
+    }
+
+    /// Vuelve a leer el fichero desde el principio, solo las lineas que caben
+    /// en una ventana de dimensiones `dim`. El resto se carga con
+    /// `load_lines` segun se va haciendo scroll.
+    pub fn file_contents(&mut self, dim: Pos<i32>) {
+        self.lines.clear();
+        self.partial.clear();
+        self.binary = false;
+        self.scroll = 0;
+        self.offset = 0;
+        self.eof = false;
+
+        self.load_lines(preview_rows(dim));
+    }
+
+    pub fn file_contents_return(&mut self, dim: Pos<i32>) -> Option<Self> {
+        if vfs::open(&self.data.path).is_err() {
+            return None;
+        }
+        self.file_contents(dim);
+        Some(self.clone())
+    }
 
-        let mut file = match File::open(&path) {
+    /// Lee del disco hasta tener al menos `n` lineas o llegar al final.
+    pub fn load_lines(&mut self, n: usize) {
+        if self.eof || self.lines.len() >= n {
+            return;
+        }
+
+        let file = match vfs::open(&self.data.path) {
             Ok(f) => f,
             Err(e) => {
-                content = format!("{} {}", "can't open file".to_string(), e);
-                return Self { data, content };
+                self.fail(e);
+                return;
             }
         };
 
-        match file.read(&mut buf) {
-            Ok(_) => {
-                if let Ok(valid_string) = String::from_utf8(buf.to_vec()) {
-                    content = valid_string.trim_end_matches('\0').to_string();
-                } else {
-                    content = "Error al convertir el buffer a UTF-8".to_string();
+        let mut buf = vec![0u8; READ_CHUNK];
+        while self.lines.len() < n && self.lines.len() < MAX_PREVIEW_LINES {
+            let read = match file.read_at(&mut buf, self.offset) {
+                Ok(read) => read,
+                Err(e) => {
+                    self.fail(e);
+                    return;
                 }
+            };
+
+            if self.offset == 0 {
+                self.binary = is_binary(&buf[..read]);
             }
-            Err(e) => {
-                content = format!("{} {}", "can't open file".to_string(), e);
-                return Self { data, content };
+            self.offset += read as u64;
+
+            if self.binary {
+                self.partial.extend_from_slice(&buf[..read]);
+                let take = match read {
+                    0 => self.partial.len(),
+                    _ => self.partial.len() - self.partial.len() % HEX_BYTES_PER_LINE,
+                };
+                let start = self.offset as usize - self.partial.len();
+                let chunk: Vec<u8> = self.partial.drain(..take).collect();
+                if !chunk.is_empty() {
+                    self.lines
+                        .extend(hex_dump(&chunk, start).split('\n').map(String::from));
+                }
+            } else {
+                for b in &buf[..read] {
+                    if *b == b'\n' {
+                        self.push_partial();
+                        continue;
+                    }
+                    if self.partial.len() >= MAX_LINE_BYTES {
+                        self.push_long_partial();
+                    }
+                    self.partial.push(*b);
+                }
+                if read == 0 && !self.partial.is_empty() {
+                    self.push_partial();
+                }
             }
-        };
 
-        Self { data, content }
+            if read == 0 {
+                self.eof = true;
+                break;
+            }
+        }
+
+        // Lo que queda solo se puede ver en el paginador
+        if !self.eof && self.lines.len() >= MAX_PREVIEW_LINES {
+            self.lines.push(format!(
+                "… preview truncated after {} lines, press Enter to open the pager",
+                MAX_PREVIEW_LINES
+            ));
+            self.eof = true;
+        }
     }
 
-    pub fn file_contents(&mut self) {
-        self.content.clear();
-        let mut buf = [0u8; FILE_READ_BYTES];
-        let file = match File::open(&self.data.path) {
-            Ok(f) => f,
-            Err(e) => {
-                self.content = format!("{} {}", "can't open file".to_string(), e);
-                return;
-            }
-        };
+    /// Carga el fichero entero. Pasadas `MAX_PREVIEW_LINES` se corta y se
+    /// avisa en la ultima linea.
+    pub fn load_all(&mut self) {
+        self.load_lines(MAX_PREVIEW_LINES);
+    }
 
-        match file.read_at(&mut buf, 0) {
-            Ok(_) => self.content = String::from_utf8_lossy(&buf).to_string(),
-            Err(e) => {
-                self.content = format!("{} {}", "can't open file".to_string(), e);
-                return;
-            }
+    pub fn is_empty(&self) -> bool {
+        self.eof && self.lines.is_empty()
+    }
+
+    fn push_partial(&mut self) {
+        let line = String::from_utf8_lossy(&self.partial).to_string();
+        self.lines.push(line);
+        self.partial.clear();
+    }
+
+    // Corta una linea demasiado larga sin partir un caracter multibyte: lo
+    // que sobra empieza la siguiente
+    fn push_long_partial(&mut self) {
+        let rest = self.partial.split_off(char_boundary(&self.partial));
+        self.push_partial();
+        self.partial = rest;
+    }
+
+    fn fail(&mut self, e: std::io::Error) {
+        self.lines = vec![format!("can't open file {}", e)];
+        self.eof = true;
+    }
+}
+
+/// Final de `buf` sin el caracter multibyte que haya quedado a medias.
+pub fn char_boundary(buf: &[u8]) -> usize {
+    for back in 1..=buf.len().min(3) {
+        let b = buf[buf.len() - back];
+        if b & 0xc0 == 0x80 {
+            continue;
+        }
+        // Primer byte de un caracter: cuantos bytes deberia tener
+        let len = match b {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return match len > back {
+            true => buf.len() - back,
+            false => buf.len(),
         };
     }
+    buf.len()
+}
 
-    pub fn file_contents_return(&mut self) -> Option<Self> {
-        let mut buf = [0u8; FILE_READ_BYTES];
-        let file = match File::open(&self.data.path) {
-            Ok(f) => f,
-            Err(_) => {
-                return None;
+/// Mensaje corto para mostrar en una ventana cuando falla una lectura.
+pub fn io_error_message(e: &std::io::Error) -> String {
+    match e.kind() {
+        ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        ErrorKind::NotFound => "No such file or directory".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// Filas de contenido que caben en una ventana de preview.
+pub fn preview_rows(dim: Pos<i32>) -> usize {
+    (dim.y - PREVIEW_MARGIN).max(1) as usize
+}
+
+/// Un buffer se considera binario si contiene un byte nulo o no es UTF-8
+/// valido. Una secuencia multibyte cortada al final del buffer no cuenta,
+/// ya que solo leemos el principio del fichero.
+pub fn is_binary(buf: &[u8]) -> bool {
+    if buf.contains(&0) {
+        return true;
+    }
+
+    match std::str::from_utf8(buf) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+/// Formato clasico de `hexdump -C`: offset, 16 bytes en hexadecimal y su
+/// representacion ASCII.
+pub fn hex_dump(buf: &[u8], offset: usize) -> String {
+    let mut out = String::with_capacity(buf.len() * 4 + buf.len() / HEX_BYTES_PER_LINE * 16);
+
+    for (i, chunk) in buf.chunks(HEX_BYTES_PER_LINE).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("{:08x}  ", offset + i * HEX_BYTES_PER_LINE));
+
+        for c in 0..HEX_BYTES_PER_LINE {
+            match chunk.get(c) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
             }
-        };
+            if c == HEX_BYTES_PER_LINE / 2 - 1 {
+                out.push(' ');
+            }
+        }
 
-        match file.read_at(&mut buf, 0) {
-            Ok(_) => self.content = String::from_utf8_lossy(&buf).to_string(),
-            Err(_) => {
-                return None;
+        out.push('|');
+        for b in chunk {
+            if b.is_ascii_graphic() || *b == b' ' {
+                out.push(*b as char);
+            } else {
+                out.push('.');
             }
-        };
-        Some(self.clone())
+        }
+        out.push('|');
+    }
+
+    out
+}
+
+/// Orden de los listados. Los directorios van siempre primero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    #[default]
+    Name,
+    /// El mas grande primero.
+    Size,
+    /// El mas reciente primero.
+    Modified,
+}
+
+impl Sort {
+    /// A partir de la tecla pulsada en la pregunta `[n]ame [s]ize
+    /// [m]odified`.
+    pub fn from_key(c: char) -> Option<Self> {
+        match c {
+            'n' => Some(Sort::Name),
+            's' => Some(Sort::Size),
+            'm' => Some(Sort::Modified),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Size => "size",
+            Sort::Modified => "modified",
+        }
     }
 }
 
@@ -126,6 +364,19 @@ impl MagFile {
 pub struct MagFolder {
     pub data: MagItem,
     pub items: Vec<MagEntry>,
+    /// Por que no se ha podido leer el directorio, si ha fallado.
+    pub error: Option<String>,
+}
+
+/// Resumen de los hijos inmediatos de un directorio para la preview. En
+/// directorios muy grandes solo se cuentan las entradas.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirSummary {
+    pub dirs: usize,
+    pub files: usize,
+    /// Suma del tamaño de los ficheros (no se baja a los subdirectorios).
+    pub total_size: u64,
+    pub newest: Option<SystemTime>,
 }
 
 impl MagFolder {
@@ -134,113 +385,140 @@ impl MagFolder {
         let data = MagItem::new(&path);
         let items = Vec::new();
 
-        Self { data, items }
+        Self {
+            data,
+            items,
+            error: None,
+        }
     }
 
     pub fn get_entries(&mut self) {
-        self.items.clear();
-        let entries = match fs::read_dir(&self.data.path) {
-            Ok(entries) => entries,
-            Err(_) => return,
-        };
-
-        for entry in entries {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => return,
-            };
-            let path = entry.path();
-
-            let metadata = match fs::metadata(entry.path()) {
-                Ok(m) => Some(m),
-                Err(_) => None,
-            };
-
-            if let Some(metadata) = metadata {
-                if metadata.is_file() {
-                    self.items.push(MagEntry::File(MagFile::new(&path)));
-                } else {
-                    self.items.push(MagEntry::Dir(MagFolder::new(&path)));
-                }
+        self.error = None;
+        self.items = match self.return_entries_result() {
+            Ok(items) => items,
+            Err(e) => {
+                self.error = Some(io_error_message(&e));
+                Vec::new()
             }
-        }
+        };
         self.sort_entries();
     }
 
+    /// Como `get_entries`, pero devuelve una copia. Si el directorio no se
+    /// puede leer se devuelve igualmente, con `error` indicando el motivo.
     pub fn get_entries_return(&mut self) -> Option<Self> {
-        self.items.clear();
-        let entries = match fs::read_dir(&self.data.path) {
-            Ok(entries) => entries,
-            Err(_) => return None,
-        };
+        self.get_entries();
+        Some(self.clone())
+    }
 
-        for entry in entries {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => return None,
-            };
-            let path = entry.path();
+    pub fn summary(&self) -> DirSummary {
+        let mut summary = DirSummary::default();
+        let with_stat = self.items.len() <= SUMMARY_MAX_STAT;
 
-            let metadata = match fs::metadata(entry.path()) {
-                Ok(m) => Some(m),
-                Err(_) => None,
+        for item in &self.items {
+            let metadata = match with_stat {
+                true => item.metadata(),
+                false => None,
             };
-
-            if let Some(metadata) = metadata {
-                if metadata.is_file() {
-                    self.items.push(MagEntry::File(MagFile::new(&path)));
-                } else {
-                    self.items.push(MagEntry::Dir(MagFolder::new(&path)));
+            match item {
+                MagEntry::Dir(_) => summary.dirs += 1,
+                MagEntry::File(_) => {
+                    summary.files += 1;
+                    summary.total_size += metadata.map_or(0, |m| m.size);
                 }
             }
+
+            if let Some(modified) = metadata.and_then(|m| m.modified) {
+                summary.newest = Some(summary.newest.map_or(modified, |n| n.max(modified)));
+            }
         }
-        self.sort_entries();
-        Some(self.clone())
+
+        summary
     }
 
     pub fn sort_entries(&mut self) {
-        self.items.sort_by(|a, b| {
-            let order_variant = a.variant_order().cmp(&b.variant_order());
-
-            if order_variant == std::cmp::Ordering::Equal {
-                a.path().cmp(b.path())
-            } else {
-                order_variant
-            }
-        });
+        self.sort_entries_by(Sort::Name);
     }
 
-    pub fn return_entries(&self) -> Option<Vec<MagEntry>> {
-        let mut v: Vec<MagEntry> = Vec::new();
+    /// Ordena por `sort`. Por tamaño o fecha hace falta un `stat` de cada
+    /// entrada.
+    pub fn sort_entries_by(&mut self, sort: Sort) {
+        self.items.sort_by(|a, b| compare(sort, a, b));
+    }
 
-        let entries = match fs::read_dir(&self.data.path) {
-            Ok(entries) => entries,
-            Err(_) => return None,
-        };
+    /// Deja solo las entradas cuyo nombre contiene `filter` (sin distinguir
+    /// mayusculas), quita las ocultas si `hidden` es `false` y ordena por
+    /// `sort`.
+    pub fn arrange(&mut self, sort: Sort, filter: Option<&str>, hidden: bool) {
+        let filter = filter.map(str::to_lowercase);
+        self.items
+            .retain(|e| is_shown(e, filter.as_deref(), hidden));
+        self.sort_entries_by(sort);
+    }
 
-        for entry in entries {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(_) => return None,
+    /// Como `arrange` pero solo con `batch`, que se mezcla con las entradas
+    /// que ya habia. Estas tienen que estar ya ordenadas.
+    pub fn merge(&mut self, batch: Vec<MagEntry>, sort: Sort, filter: Option<&str>, hidden: bool) {
+        let filter = filter.map(str::to_lowercase);
+        let mut batch: Vec<MagEntry> = batch
+            .into_iter()
+            .filter(|e| is_shown(e, filter.as_deref(), hidden))
+            .collect();
+        batch.sort_by(|a, b| compare(sort, a, b));
+
+        let old = std::mem::take(&mut self.items);
+        let mut items = Vec::with_capacity(old.len() + batch.len());
+        let mut old = old.into_iter().peekable();
+        let mut batch = batch.into_iter().peekable();
+        while let (Some(a), Some(b)) = (old.peek(), batch.peek()) {
+            let next = match compare(sort, b, a).is_lt() {
+                true => batch.next(),
+                false => old.next(),
             };
-            let path = entry.path();
+            items.extend(next);
+        }
+        items.extend(old);
+        items.extend(batch);
+        self.items = items;
+    }
 
-            let metadata = match fs::metadata(entry.path()) {
-                Ok(m) => Some(m),
-                Err(_) => None,
-            };
+    pub fn return_entries(&self) -> Option<Vec<MagEntry>> {
+        self.return_entries_result().ok()
+    }
 
-            if let Some(metadata) = metadata {
-                if metadata.is_file() {
-                    v.push(MagEntry::File(MagFile::new(&path)));
-                } else {
-                    v.push(MagEntry::Dir(MagFolder::new(&path)));
-                }
-            }
+    fn return_entries_result(&self) -> std::io::Result<Vec<MagEntry>> {
+        let entries = vfs::list(&self.data.path)?;
+        Ok(entries.into_iter().map(MagEntry::from_entry).collect())
+    }
+}
+
+fn compare(sort: Sort, a: &MagEntry, b: &MagEntry) -> std::cmp::Ordering {
+    let order_variant = a.variant_order().cmp(&b.variant_order());
+    let by_key = match sort {
+        Sort::Name => std::cmp::Ordering::Equal,
+        Sort::Size => {
+            let size = |e: &MagEntry| e.metadata().map_or(0, |m| m.size);
+            size(b).cmp(&size(a))
         }
+        Sort::Modified => {
+            let time = |e: &MagEntry| e.metadata().and_then(|m| m.modified);
+            time(b).cmp(&time(a))
+        }
+    };
 
-        Some(v)
-    }
+    order_variant
+        .then(by_key)
+        .then_with(|| a.path().cmp(b.path()))
+}
+
+// `filter` ya en minusculas
+fn is_shown(entry: &MagEntry, filter: Option<&str>, hidden: bool) -> bool {
+    let name = entry
+        .path()
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    (hidden || !name.starts_with('.')) && filter.is_none_or(|f| name.to_lowercase().contains(f))
 }
 
 #[derive(Debug, Clone)]
@@ -250,9 +528,26 @@ pub enum MagEntry {
 }
 
 impl MagEntry {
-    pub fn get_folder(&self) -> Option<MagFolder> {
+    pub fn from_entry(entry: vfs::DirEntry) -> Self {
+        let is_dir = entry.kind == vfs::Kind::Dir;
+        let data = MagItem::from(entry.path, entry.stat);
+
+        match is_dir {
+            true => MagEntry::Dir(MagFolder {
+                data,
+                items: Vec::new(),
+                error: None,
+            }),
+            false => MagEntry::File(MagFile {
+                data,
+                ..MagFile::new_empty()
+            }),
+        }
+    }
+
+    pub fn get_folder(&self) -> Option<&MagFolder> {
         match self {
-            MagEntry::Dir(d) => Some(d.clone()),
+            MagEntry::Dir(d) => Some(d),
             MagEntry::File(_) => None,
         }
     }
@@ -284,6 +579,20 @@ impl MagEntry {
         }
     }
 
+    pub fn metadata(&self) -> Option<&Stat> {
+        match self {
+            MagEntry::Dir(folder) => folder.data.metadata(),
+            MagEntry::File(file) => file.data.metadata(),
+        }
+    }
+
+    pub fn forget_metadata(&mut self) {
+        match self {
+            MagEntry::Dir(folder) => folder.data.forget_metadata(),
+            MagEntry::File(file) => file.data.forget_metadata(),
+        }
+    }
+
     pub fn path(&self) -> &Path {
         match self {
             MagEntry::Dir(folder) => &folder.data.path,
@@ -305,6 +614,17 @@ impl MagEntry {
         }
     }
 
+    /// `true` si al seleccionarla se muestra como un directorio: las carpetas
+    /// y los archivos comprimidos, que se pueden recorrer por dentro.
+    pub fn is_browsable(&self) -> bool {
+        match self {
+            MagEntry::Dir(_) => true,
+            MagEntry::File(f) => {
+                archive::is_archive(&f.data.path) && !vfs::is_virtual(&f.data.path)
+            }
+        }
+    }
+
     pub fn is_folder_empty(&self) -> bool {
         match self {
             MagEntry::Dir(d) => d.items.is_empty(),
@@ -316,11 +636,179 @@ impl MagEntry {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::memfs::MemoryFs;
+    use std::fs;
 
     #[test]
     fn file() {
         let mut f = MagFile::new("/home/mikel/Escritorio/ruby/rb/rb/main.rb");
-        f.file_contents();
-        println!("{}", f.content);
+        f.file_contents(Pos::new(80, 24));
+        println!("{}", f.lines.join("\n"));
+    }
+
+    #[test]
+    fn lazy_lines() {
+        let path = std::env::temp_dir().join(format!("mag-lazy-{}", std::process::id()));
+        let text: String = (0..1000).map(|i| format!("linea {}\n", i)).collect();
+        fs::write(&path, text + "sin salto").unwrap();
+
+        let mut f = MagFile::new_return(&path, Pos::new(80, 13));
+        assert!(f.lines.len() >= 10);
+        assert!(!f.eof);
+        assert_eq!(f.lines[3], "linea 3");
+
+        f.load_all();
+        assert!(f.eof);
+        assert_eq!(f.lines.len(), 1001);
+        assert_eq!(f.lines[1000], "sin salto");
+
+        fs::write(&path, [0u8; 40]).unwrap();
+        let mut f = MagFile::new_return(&path, Pos::new(80, 13));
+        f.load_all();
+        assert!(f.binary);
+        assert_eq!(f.lines.len(), 3);
+        assert!(f.lines[2].starts_with("00000020  00 00 00 00 "));
+
+        // Una linea larga se parte sin cortar las "ñ"
+        let long = "a".to_string() + &"ñ".repeat(MAX_LINE_BYTES);
+        fs::write(&path, &long).unwrap();
+        let mut f = MagFile::new_return(&path, Pos::new(80, 13));
+        f.load_all();
+        assert!(f.lines.len() > 1);
+        assert!(f.lines.iter().all(|l| !l.contains('\u{fffd}')));
+        assert_eq!(f.lines.concat(), long);
+        assert_eq!(char_boundary("aé".as_bytes()), 3);
+        assert_eq!(char_boundary(&"aé".as_bytes()[..2]), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dir_summary() {
+        let dir = std::env::temp_dir().join(format!("mag-summary-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a"), [0u8; 10]).unwrap();
+        fs::write(dir.join("b"), [0u8; 5]).unwrap();
+
+        let folder = MagFolder::new(&dir).get_entries_return().unwrap();
+        let summary = folder.summary();
+        assert_eq!((summary.dirs, summary.files), (1, 2));
+        assert_eq!(summary.total_size, 15);
+        assert!(summary.newest.is_some());
+
+        let missing = MagFolder::new(dir.join("nope"))
+            .get_entries_return()
+            .unwrap();
+        assert!(missing.items.is_empty());
+        assert!(missing.error.is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn arrange() {
+        let dir = std::env::temp_dir().join(format!("mag-arrange-{}", std::process::id()));
+        fs::create_dir_all(dir.join("zdir")).unwrap();
+        fs::write(dir.join("a.txt"), [0u8; 1]).unwrap();
+        fs::write(dir.join("B.TXT"), [0u8; 30]).unwrap();
+        fs::write(dir.join("c.rs"), [0u8; 20]).unwrap();
+        fs::write(dir.join(".oculto.txt"), "").unwrap();
+
+        let names = |f: &MagFolder| -> Vec<String> {
+            f.items
+                .iter()
+                .map(|e| e.path().file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        let mut folder = MagFolder::new(&dir).get_entries_return().unwrap();
+        assert_eq!(
+            names(&folder),
+            ["zdir", ".oculto.txt", "B.TXT", "a.txt", "c.rs"]
+        );
+        folder.arrange(Sort::Size, None, false);
+        assert_eq!(names(&folder), ["zdir", "B.TXT", "c.rs", "a.txt"]);
+        folder.arrange(Sort::Name, Some("txt"), false);
+        assert_eq!(names(&folder), ["B.TXT", "a.txt"]);
+
+        // Las tandas de una carga se mezclan con lo ya ordenado
+        let all = MagFolder::new(&dir).get_entries_return().unwrap().items;
+        let mut merged = MagFolder::new(&dir);
+        let (first, second) = all.split_at(2);
+        merged.merge(second.to_vec(), Sort::Size, None, false);
+        merged.merge(first.to_vec(), Sort::Size, None, false);
+        assert_eq!(names(&merged), ["zdir", "B.TXT", "c.rs", "a.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_fs() {
+        let root = PathBuf::from("/mag-memfs-test");
+        let memfs = std::sync::Arc::new(MemoryFs::new());
+        memfs.add_file(root.join("docs/notas.txt"), "uno\ndos\n");
+        memfs.add_file(root.join("b.bin"), vec![0u8, 1, 2]);
+        memfs.add_symlink(root.join("enlace"), "docs/notas.txt");
+        memfs.add_symlink(root.join("atajo"), "docs");
+        vfs::mount(&root, memfs);
+
+        let folder = MagFolder::new(&root).get_entries_return().unwrap();
+        assert!(folder.error.is_none());
+        let names: Vec<String> = folder
+            .items
+            .iter()
+            .map(|e| e.path().file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["atajo", "docs", "b.bin", "enlace"]);
+
+        // Un directorio enlazado se lista con sus hijos bajo la ruta del enlace
+        let linked = MagFolder::new(root.join("atajo"))
+            .get_entries_return()
+            .unwrap();
+        assert!(linked.error.is_none());
+        let paths: Vec<PathBuf> = linked.items.iter().map(|e| e.path().to_owned()).collect();
+        assert_eq!(paths, [root.join("atajo/notas.txt")]);
+        let f = MagFile::new_return(root.join("atajo/notas.txt"), Pos::new(80, 24));
+        assert_eq!(f.lines, ["uno", "dos"]);
+
+        let link = folder.items[3].get_file().unwrap();
+        assert_eq!(
+            link.data.link_target(),
+            Some(PathBuf::from("docs/notas.txt"))
+        );
+
+        let f = MagFile::new_return(root.join("enlace"), Pos::new(80, 24));
+        assert_eq!(f.lines, ["uno", "dos"]);
+        let f = MagFile::new_return(root.join("b.bin"), Pos::new(80, 24));
+        assert!(f.binary);
+
+        let missing = MagFolder::new(root.join("nope"))
+            .get_entries_return()
+            .unwrap();
+        assert!(missing.error.is_some());
+        assert!(vfs::is_virtual(&root.join("docs")));
+
+        vfs::unmount(&root);
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary(b"hola mundo\n"));
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(is_binary(&[0xff, 0xfe, b'a']));
+        // "ñ" cortada a la mitad al final del buffer
+        assert!(!is_binary(&[b'a', 0xc3]));
+    }
+
+    #[test]
+    fn hex() {
+        let dump = hex_dump(b"0123456789abcdefXY", 0x10);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000010  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66 |0123456789abcdef|"
+        );
+        assert!(lines[1].starts_with("00000020  58 59 "));
+        assert!(lines[1].ends_with("|XY|"));
     }
 }
@@ -0,0 +1,371 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::config;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+// Cuando la suma de todas las puntuaciones pasa de aqui se reducen todas,
+// para que lo que se dejo de visitar acabe desapareciendo.
+const MAX_RANK: f64 = 10_000.0;
+// Los cambios se escriben como mucho este tiempo despues, no en cada visita.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    rank: f64,
+    last: u64,
+}
+
+/// Directorios visitados con cuantas veces y cuando, para saltar al que
+/// mejor encaje con unas palabras igual que `z` o zoxide.
+///
+/// Se guarda en `$XDG_DATA_HOME/mag/frecency` con el formato de `z`
+/// (`ruta|puntuacion|fecha` por linea). Al guardar se suma lo de esta
+/// sesion a lo que haya en el fichero, asi que varias instancias no se pisan.
+#[derive(Debug, Default)]
+pub struct Frecency {
+    dirs: HashMap<PathBuf, Entry>,
+    file: Option<PathBuf>,
+    // Lo que falta por escribir: puntuacion añadida y directorios borrados
+    added: HashMap<PathBuf, Entry>,
+    removed: HashSet<PathBuf>,
+    dirty_since: Option<Instant>,
+}
+
+impl Frecency {
+    /// Si todavia no hay base de datos y en la config esta `import_z`, se
+    /// empieza con lo que haya en ese fichero de z o zoxide.
+    pub fn load() -> Self {
+        let Some(file) = config::data_dir().map(|d| d.join("frecency")) else {
+            return Self::default();
+        };
+
+        let mut db = Self::from_file(&file);
+        if !file.exists() {
+            if let Some(import) = &config::get().import_z {
+                if db.import(import).is_ok() {
+                    db.flush();
+                }
+            }
+        }
+        db
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            dirs: read(path.as_ref()),
+            file: Some(path.as_ref().to_owned()),
+            ..Self::default()
+        }
+    }
+
+    /// Añade las entradas de una base de datos de zoxide (`db.zo`) o de z
+    /// (`~/.z`). Si un directorio ya estaba se suman las puntuaciones.
+    pub fn import<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let bytes = fs::read(path)?;
+        let dirs = match parse_zoxide(&bytes) {
+            Some(dirs) => dirs,
+            None => parse_z(&String::from_utf8_lossy(&bytes)),
+        };
+        let n = dirs.len();
+        for (path, e) in dirs {
+            self.add(path, e);
+        }
+        Ok(n)
+    }
+
+    pub fn visit<P: AsRef<Path>>(&mut self, dir: P) {
+        let visit = Entry {
+            rank: 1.0,
+            last: now(),
+        };
+        self.add(dir.as_ref().to_owned(), visit);
+        age(&mut self.dirs);
+    }
+
+    fn add(&mut self, path: PathBuf, e: Entry) {
+        self.removed.remove(&path);
+        merge(&mut self.dirs, path.clone(), &e);
+        merge(&mut self.added, path, &e);
+        self.dirty_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Los directorios que encajan con `keywords`, el mejor primero.
+    pub fn query(&self, keywords: &[&str]) -> Vec<PathBuf> {
+        let keywords: Vec<String> = keywords.iter().map(|k| k.to_lowercase()).collect();
+        let now = now();
+
+        let mut found: Vec<(f64, &PathBuf)> = self
+            .dirs
+            .iter()
+            .filter(|(p, _)| matches(&p.to_string_lossy(), &keywords))
+            .map(|(p, e)| (score(e, now), p))
+            .collect();
+        found.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        found.into_iter().map(|(_, p)| p.clone()).collect()
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, dir: P) {
+        let dir = dir.as_ref();
+        if self.dirs.remove(dir).is_some() {
+            self.added.remove(dir);
+            self.removed.insert(dir.to_owned());
+            self.dirty_since.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Guarda si hay cambios pendientes desde hace `SAVE_DELAY`. Se llama en
+    /// cada vuelta del bucle principal.
+    pub fn autosave(&mut self) {
+        if self.dirty_since.is_some_and(|t| t.elapsed() >= SAVE_DELAY) {
+            self.flush();
+        }
+    }
+
+    /// Aplica los cambios pendientes sobre lo que haya en el fichero y lo
+    /// escribe. Si no se puede escribir simplemente no se guarda.
+    pub fn flush(&mut self) {
+        self.dirty_since = None;
+        let added = std::mem::take(&mut self.added);
+        let removed = std::mem::take(&mut self.removed);
+        let Some(file) = &self.file else {
+            return;
+        };
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        let mut dirs = read(file);
+        dirs.retain(|p, _| !removed.contains(p));
+        for (path, e) in added {
+            merge(&mut dirs, path, &e);
+        }
+        age(&mut dirs);
+
+        let mut out = String::new();
+        for (path, e) in &dirs {
+            // El formato de z es texto; las rutas raras no se guardan
+            match path.to_str() {
+                Some(p) if !p.contains('\n') => {
+                    out.push_str(&format!("{}|{}|{}\n", p, e.rank, e.last))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, out);
+        // Asi tambien se ve lo que han guardado otras instancias
+        self.dirs = dirs;
+    }
+}
+
+fn read(file: &Path) -> HashMap<PathBuf, Entry> {
+    let mut dirs = HashMap::new();
+    if let Ok(s) = fs::read_to_string(file) {
+        for (path, e) in parse_z(&s) {
+            merge(&mut dirs, path, &e);
+        }
+    }
+    dirs
+}
+
+// Si un directorio ya estaba se suman las puntuaciones
+fn merge(dirs: &mut HashMap<PathBuf, Entry>, path: PathBuf, e: &Entry) {
+    let entry = dirs.entry(path).or_insert(Entry { rank: 0.0, last: 0 });
+    entry.rank += e.rank;
+    entry.last = entry.last.max(e.last);
+}
+
+// Si la suma pasa de `MAX_RANK` se reducen todas
+fn age(dirs: &mut HashMap<PathBuf, Entry>) {
+    let total: f64 = dirs.values().map(|e| e.rank).sum();
+    if total > MAX_RANK {
+        let factor = 0.9 * MAX_RANK / total;
+        for e in dirs.values_mut() {
+            e.rank *= factor;
+        }
+        dirs.retain(|_, e| e.rank >= 1.0);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Igual que zoxide: lo visitado hace poco vale mas
+fn score(e: &Entry, now: u64) -> f64 {
+    let age = now.saturating_sub(e.last);
+    if age < HOUR {
+        e.rank * 4.0
+    } else if age < DAY {
+        e.rank * 2.0
+    } else if age < WEEK {
+        e.rank / 2.0
+    } else {
+        e.rank / 4.0
+    }
+}
+
+/// Las palabras tienen que aparecer en la ruta en el mismo orden y la ultima
+/// en el ultimo componente, como en zoxide. Sin palabras encaja todo.
+pub fn matches(path: &str, keywords: &[String]) -> bool {
+    let Some((last, rest)) = keywords.split_last() else {
+        return true;
+    };
+    let path = path.to_lowercase();
+    let mut path = path.as_str();
+
+    match path.rfind(last.as_str()) {
+        Some(i) if !path[i + last.len()..].contains('/') => path = &path[..i],
+        _ => return false,
+    }
+    for keyword in rest.iter().rev() {
+        match path.rfind(keyword.as_str()) {
+            Some(i) => path = &path[..i],
+            None => return false,
+        }
+    }
+    true
+}
+
+fn parse_z(s: &str) -> Vec<(PathBuf, Entry)> {
+    s.lines()
+        .filter_map(|line| {
+            // La ruta puede tener '|', asi que se parte por la derecha
+            let mut parts = line.rsplitn(3, '|');
+            let last = parts.next()?.trim().parse().ok()?;
+            let rank = parts.next()?.trim().parse().ok()?;
+            let path = parts.next()?;
+            Some((PathBuf::from(path), Entry { rank, last }))
+        })
+        .collect()
+}
+
+// Formato de zoxide (version 3, bincode): u32 con la version y luego la
+// lista de directorios, cada uno con la ruta, la puntuacion (f64) y la fecha
+// del ultimo acceso (u64). Todo en little endian y las longitudes en u64.
+fn parse_zoxide(bytes: &[u8]) -> Option<Vec<(PathBuf, Entry)>> {
+    let mut r = Reader(bytes);
+    if r.u32()? != 3 {
+        return None;
+    }
+
+    let n = r.u64()?;
+    let mut dirs = Vec::new();
+    for _ in 0..n {
+        let len = r.u64()? as usize;
+        let path = std::str::from_utf8(r.take(len)?).ok()?;
+        let rank = f64::from_le_bytes(r.take(8)?.try_into().ok()?);
+        let last = r.u64()?;
+        dirs.push((PathBuf::from(path), Entry { rank, last }));
+    }
+    Some(dirs)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(k: &[&str]) -> Vec<String> {
+        k.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn matching() {
+        assert!(matches("/home/ana/src/mag", &keywords(&["mag"])));
+        assert!(matches("/home/ana/src/mag", &keywords(&["src", "mag"])));
+        assert!(matches("/home/ana/Src/Mag", &keywords(&["src", "ma"])));
+        // La ultima palabra tiene que estar en el ultimo componente
+        assert!(!matches("/home/ana/src/mag", &keywords(&["src"])));
+        assert!(!matches("/home/ana/src/mag", &keywords(&["mag", "src"])));
+        assert!(matches("/x", &[]));
+    }
+
+    #[test]
+    fn ranking_and_import() {
+        let dir = std::env::temp_dir().join(format!("mag-frecency-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut db = Frecency::from_file(dir.join("frecency"));
+        db.visit("/src/mag");
+        db.visit("/src/mag");
+        db.visit("/src/magia");
+        assert_eq!(
+            db.query(&["mag"]),
+            [PathBuf::from("/src/mag"), PathBuf::from("/src/magia")]
+        );
+
+        // Lo que se visito hace un mes cuenta menos aunque se visitara mas
+        let old = now() - 30 * DAY;
+        fs::write(dir.join("z"), format!("/old/magia|10|{}\nroto\n", old)).unwrap();
+        assert_eq!(db.import(dir.join("z")).unwrap(), 1);
+        assert_eq!(db.query(&["magia"])[0], PathBuf::from("/src/magia"));
+
+        let mut zo = Vec::new();
+        zo.extend_from_slice(&3u32.to_le_bytes());
+        zo.extend_from_slice(&1u64.to_le_bytes());
+        zo.extend_from_slice(&7u64.to_le_bytes());
+        zo.extend_from_slice(b"/zo/mag");
+        zo.extend_from_slice(&50f64.to_le_bytes());
+        zo.extend_from_slice(&now().to_le_bytes());
+        fs::write(dir.join("db.zo"), zo).unwrap();
+        assert_eq!(db.import(dir.join("db.zo")).unwrap(), 1);
+        assert_eq!(db.query(&["mag"])[0], PathBuf::from("/zo/mag"));
+
+        // No se escribe nada hasta `flush`
+        assert!(Frecency::from_file(dir.join("frecency")).dirs.is_empty());
+        db.flush();
+        let mut other = Frecency::from_file(dir.join("frecency"));
+        assert_eq!(other.query(&["mag"]).len(), 4);
+
+        // Los cambios de dos instancias se suman en el fichero
+        other.visit("/otra/mag");
+        db.remove("/zo/mag");
+        db.remove("/old/magia");
+        other.flush();
+        db.flush();
+        let db = Frecency::from_file(dir.join("frecency"));
+        assert_eq!(
+            db.query(&["mag"]),
+            [
+                PathBuf::from("/src/mag"),
+                PathBuf::from("/otra/mag"),
+                PathBuf::from("/src/magia")
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{config, vfs};
+
+// Entradas que se guardan como mucho; las mas antiguas se pierden.
+const MAX_ENTRIES: usize = 500;
+// Lo visitado se escribe como mucho este tiempo despues, no en cada tecla.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// Directorios visitados, como el historial de un navegador: `back` y
+/// `forward` se mueven por la lista y visitar uno nuevo descarta los que
+/// habia por delante.
+///
+/// Se guarda en `$XDG_DATA_HOME/mag/history`, una ruta por linea, de la mas
+/// antigua a la mas reciente. Al guardar se añade a lo que haya en el
+/// fichero, asi que varias instancias no se pisan.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<PathBuf>,
+    pos: usize,
+    file: Option<PathBuf>,
+    // Visitas todavia sin escribir y desde cuando
+    pending: Vec<PathBuf>,
+    dirty_since: Option<Instant>,
+}
+
+impl History {
+    pub fn load() -> Self {
+        match config::data_dir() {
+            Some(dir) => Self::from_file(dir.join("history")),
+            None => Self::default(),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
+        let entries: Vec<PathBuf> = match fs::read(path.as_ref()) {
+            Ok(bytes) => bytes
+                .split(|b| *b == b'\n')
+                .filter(|l| !l.is_empty())
+                .map(|l| PathBuf::from(OsStr::from_bytes(l)))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            pos: entries.len().saturating_sub(1),
+            entries,
+            file: Some(path.as_ref().to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// Posicion actual dentro de `entries`.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn visit<P: AsRef<Path>>(&mut self, dir: P) {
+        let dir = dir.as_ref();
+        if self.entries.get(self.pos).is_some_and(|p| p == dir) {
+            return;
+        }
+
+        self.entries.truncate(self.pos + 1);
+        self.entries.push(dir.to_owned());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        self.pos = self.entries.len() - 1;
+        self.pending.push(dir.to_owned());
+        self.dirty_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Vuelve al anterior directorio que todavia exista.
+    pub fn back(&mut self) -> Option<PathBuf> {
+        let found = (0..self.pos).rev().find(|&i| is_dir(&self.entries[i]))?;
+        self.pos = found;
+        Some(self.entries[found].clone())
+    }
+
+    pub fn forward(&mut self) -> Option<PathBuf> {
+        let found = (self.pos + 1..self.entries.len()).find(|&i| is_dir(&self.entries[i]))?;
+        self.pos = found;
+        Some(self.entries[found].clone())
+    }
+
+    /// Guarda si hay visitas pendientes desde hace `SAVE_DELAY`. Se llama en
+    /// cada vuelta del bucle principal.
+    pub fn autosave(&mut self) {
+        if self.dirty_since.is_some_and(|t| t.elapsed() >= SAVE_DELAY) {
+            self.flush();
+        }
+    }
+
+    /// Añade las visitas pendientes al final de lo que haya en el fichero.
+    /// Si no se puede escribir el historial simplemente no se guarda.
+    pub fn flush(&mut self) {
+        self.dirty_since = None;
+        let pending = std::mem::take(&mut self.pending);
+        let Some(file) = &self.file else {
+            return;
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        let mut entries = Self::from_file(file).entries;
+        for p in pending {
+            if entries.last() != Some(&p) {
+                entries.push(p);
+            }
+        }
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+
+        let mut out = Vec::new();
+        for p in &entries {
+            let bytes = p.as_os_str().as_bytes();
+            // Una ruta con saltos de linea romperia el formato
+            if !bytes.contains(&b'\n') {
+                out.extend_from_slice(bytes);
+                out.push(b'\n');
+            }
+        }
+
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, out);
+    }
+}
+
+fn is_dir(path: &Path) -> bool {
+    vfs::stat(path).is_ok_and(|s| s.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_forward() {
+        let dir = std::env::temp_dir().join(format!("mag-history-{}", std::process::id()));
+        for d in ["a", "b", "c", "d"] {
+            fs::create_dir_all(dir.join(d)).unwrap();
+        }
+        let file = dir.join("history");
+
+        let mut h = History::from_file(&file);
+        assert!(h.back().is_none());
+        h.visit(dir.join("a"));
+        h.visit(dir.join("b"));
+        h.visit(dir.join("c"));
+        assert_eq!(h.back(), Some(dir.join("b")));
+        assert_eq!(h.back(), Some(dir.join("a")));
+        assert_eq!(h.forward(), Some(dir.join("b")));
+
+        // Visitar otro descarta lo que habia por delante
+        h.visit(dir.join("d"));
+        assert!(h.forward().is_none());
+        assert_eq!(h.entries().len(), 3);
+
+        // Los que ya no existen se saltan
+        fs::remove_dir(dir.join("b")).unwrap();
+        assert_eq!(h.back(), Some(dir.join("a")));
+
+        // No se escribe nada hasta `flush`
+        assert!(History::from_file(&file).entries().is_empty());
+        h.flush();
+        let h2 = History::from_file(&file);
+        assert_eq!(
+            h2.entries(),
+            [dir.join("a"), dir.join("b"), dir.join("c"), dir.join("d")]
+        );
+        assert_eq!(h2.pos(), 3);
+
+        // Lo que guarda otra instancia a la vez se conserva
+        let mut other = History::from_file(&file);
+        other.visit(dir.join("c"));
+        h.visit(dir.join("d"));
+        other.flush();
+        h.flush();
+        let h = History::from_file(&file);
+        assert_eq!(h.entries()[4..], [dir.join("c"), dir.join("d")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    io::{self, Read},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct JobStatus {
+    pub name: String,
+    pub done: u64,
+    pub total: u64,
+    pub finished: bool,
+    /// Resumen al acabar, o el error si ha fallado.
+    pub result: Option<Result<String, String>>,
+}
+
+impl JobStatus {
+    pub fn percent(&self) -> u64 {
+        match self.total {
+            0 => 0,
+            t => (self.done * 100 / t).min(100),
+        }
+    }
+}
+
+/// Lo que un trabajo en segundo plano usa para informar de su avance.
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Arc<Mutex<JobStatus>>);
+
+impl Progress {
+    pub fn set_total(&self, total: u64) {
+        self.0.lock().unwrap().total = total;
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.lock().unwrap().done += n;
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Envuelve un lector y va sumando al progreso los bytes leidos.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.add(n as u64);
+        Ok(n)
+    }
+}
+
+/// Trabajos lanzados en hilos aparte (extraer, comprimir...) para no
+/// bloquear la interfaz.
+#[derive(Debug, Default)]
+pub struct Jobs {
+    running: Vec<Progress>,
+}
+
+impl Jobs {
+    pub fn spawn<F>(&mut self, name: String, f: F)
+    where
+        F: FnOnce(&Progress) -> io::Result<String> + Send + 'static,
+    {
+        let progress = Progress::default();
+        progress.0.lock().unwrap().name = name;
+        self.running.push(progress.clone());
+
+        thread::spawn(move || {
+            let result = f(&progress).map_err(|e| e.to_string());
+            let mut status = progress.0.lock().unwrap();
+            status.finished = true;
+            status.result = Some(result);
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    /// `extract foo.zip 40% | compress bar.tar.gz 3%`
+    pub fn status_line(&self) -> Option<String> {
+        if self.running.is_empty() {
+            return None;
+        }
+
+        let parts: Vec<String> = self
+            .running
+            .iter()
+            .map(|p| {
+                let s = p.status();
+                format!("{} {}%", s.name, s.percent())
+            })
+            .collect();
+        Some(parts.join(" | "))
+    }
+
+    /// Quita y devuelve los trabajos que ya han terminado.
+    pub fn take_finished(&mut self) -> Vec<JobStatus> {
+        let mut finished = Vec::new();
+        self.running.retain(|p| {
+            let s = p.status();
+            if s.finished {
+                finished.push(s);
+                false
+            } else {
+                true
+            }
+        });
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn spawn_and_finish() {
+        let mut jobs = Jobs::default();
+        jobs.spawn("test".to_string(), |p| {
+            p.set_total(10);
+            let mut r = ProgressReader::new(&[0u8; 10][..], p);
+            io::copy(&mut r, &mut io::sink())?;
+            Ok("hecho".to_string())
+        });
+
+        let mut finished = Vec::new();
+        for _ in 0..100 {
+            finished = jobs.take_finished();
+            if !finished.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].percent(), 100);
+        assert_eq!(finished[0].result, Some(Ok("hecho".to_string())));
+        assert!(jobs.is_empty());
+    }
+}
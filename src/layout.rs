@@ -0,0 +1,279 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{config, pos::Pos, state::State, ui::MagWindow};
+
+// Fila 0: las pestañas; fila 1: la ruta
+const START_TOP: i32 = 2;
+/// Columnas de directorios padre que se pueden mostrar como mucho.
+pub const MAX_PARENTS: usize = 4;
+const MAX_RATIO: u32 = 9;
+
+/// Las ventanas de una pestaña.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Parent,
+    Mid,
+    Child,
+    /// El segundo panel del modo de dos paneles.
+    Other,
+}
+
+/// Como se reparten las ventanas de la pestaña activa en la pantalla. La
+/// ventana activa siempre es `mid_win`; el resto de `State` no sabe que
+/// columnas se ven ni donde.
+pub trait Layout: fmt::Debug + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Coloca las ventanas para una pantalla de `w` columnas y `h` filas.
+    fn place(&self, state: &mut State, w: i32, h: i32);
+
+    fn display(&self, state: &State);
+
+    fn shows(&self, state: &State, pane: Pane) -> bool;
+
+    /// Teclas propias de la disposicion. Devuelve `true` si la ha usado.
+    fn handle_key(&self, _state: &mut State, _ch: i32) -> bool {
+        false
+    }
+
+    /// Directorio que se propone al copiar o mover.
+    fn target_dir(&self, state: &State) -> PathBuf {
+        state.mid_win.path.clone()
+    }
+}
+
+/// Columnas de la disposicion de Miller. Se leen de la config y se pueden
+/// cambiar con teclas mientras se usa.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    /// Cuantos niveles de directorios padre se ven a la izquierda.
+    pub parents: usize,
+    /// Proporcion de cada columna de padres, de la del medio y de la preview.
+    pub ratios: [u32; 3],
+    pub preview: bool,
+    /// Solo se ve la preview, a todo el ancho.
+    pub maximized: bool,
+}
+
+impl Columns {
+    pub fn from_config() -> Self {
+        let config = config::get();
+        Self {
+            parents: config.parent_columns.min(MAX_PARENTS),
+            ratios: config.column_ratios,
+            preview: config.preview,
+            maximized: false,
+        }
+    }
+
+    /// Ancho de cada columna que se ve, de izquierda a derecha, repartiendo
+    /// `total` segun las proporciones.
+    pub fn widths(&self, total: i32) -> Vec<i32> {
+        let mut weights = vec![self.ratios[0]; self.parents];
+        weights.push(self.ratios[1]);
+        if self.preview {
+            weights.push(self.ratios[2]);
+        }
+        let sum = weights.iter().sum::<u32>().max(1) as i32;
+
+        let mut widths: Vec<i32> = weights.iter().map(|w| total * *w as i32 / sum).collect();
+        // Lo que sobra al redondear para la ultima
+        let used: i32 = widths.iter().sum();
+        if let Some(last) = widths.last_mut() {
+            *last += total - used;
+        }
+        widths
+    }
+
+    /// Cambia la proporcion de una columna respecto al resto: 0 son los
+    /// padres, 1 la del medio y 2 la preview.
+    pub fn resize(&mut self, column: usize, delta: i32) {
+        self.ratios[column] = self.ratios[column]
+            .saturating_add_signed(delta)
+            .clamp(1, MAX_RATIO);
+    }
+}
+
+pub static LAYOUTS: [&dyn Layout; 2] = [&Miller, &Commander];
+
+pub fn by_name(name: &str) -> Option<&'static dyn Layout> {
+    LAYOUTS.iter().copied().find(|l| l.name() == name)
+}
+
+/// Columnas: los directorios padre, el actual y la preview.
+#[derive(Debug)]
+pub struct Miller;
+
+impl Layout for Miller {
+    fn name(&self) -> &'static str {
+        "miller"
+    }
+
+    fn place(&self, state: &mut State, w: i32, h: i32) {
+        let height = h - START_TOP;
+        let parents = state.columns.parents;
+        let preview = state.columns.preview;
+
+        let mut rects = Vec::new();
+        if state.columns.maximized {
+            rects.push((Pos::new(1, START_TOP), Pos::new(w - 1, height)));
+        } else {
+            let mut x = 1;
+            for width in state.columns.widths(w - 1) {
+                rects.push((Pos::new(x, START_TOP), Pos::new(width, height)));
+                x += width;
+            }
+        }
+
+        // De izquierda a derecha: los padres de mas lejos a mas cerca, la del
+        // medio y la preview
+        let mut columns: Vec<&mut MagWindow> = Vec::new();
+        if state.columns.maximized {
+            columns.push(&mut state.child_win);
+        } else {
+            columns.extend(state.ancestors.iter_mut().rev());
+            if parents > 0 {
+                columns.push(&mut state.parent_win);
+            }
+            columns.push(&mut state.mid_win);
+            if preview {
+                columns.push(&mut state.child_win);
+            }
+        }
+        for (win, (coord, dim)) in columns.into_iter().zip(rects) {
+            win.change_dim(coord, dim);
+        }
+
+        // Lo que no se ve va donde la preview o la del medio: al moverse
+        // pueden pasar a serlo
+        let rect = match state.columns.maximized {
+            true => (state.child_win.coord, state.child_win.dimensions),
+            false => (state.mid_win.coord, state.mid_win.dimensions),
+        };
+        if state.columns.maximized {
+            state.parent_win.change_dim(rect.0, rect.1);
+            state.mid_win.change_dim(rect.0, rect.1);
+        } else {
+            if parents == 0 {
+                state.parent_win.change_dim(rect.0, rect.1);
+            }
+            if !preview {
+                state.child_win.change_dim(rect.0, rect.1);
+            }
+        }
+    }
+
+    fn display(&self, state: &State) {
+        if self.shows(state, Pane::Parent) {
+            for win in &state.ancestors {
+                win.display();
+            }
+            state.parent_win.display();
+        }
+        state.display_mid();
+        state.display_child();
+    }
+
+    fn shows(&self, state: &State, pane: Pane) -> bool {
+        let columns = &state.columns;
+        match pane {
+            Pane::Parent => columns.parents > 0 && !columns.maximized,
+            Pane::Mid => !columns.maximized,
+            Pane::Child => columns.preview || columns.maximized,
+            Pane::Other => false,
+        }
+    }
+}
+
+/// Dos paneles independientes, como Midnight Commander. `o` cambia de panel
+/// (Tab sigue siendo la siguiente pestaña, como en Miller) y copiar o mover
+/// propone el directorio del otro.
+#[derive(Debug)]
+pub struct Commander;
+
+impl Layout for Commander {
+    fn name(&self) -> &'static str {
+        "commander"
+    }
+
+    fn place(&self, state: &mut State, w: i32, h: i32) {
+        // El otro panel empieza en el mismo directorio
+        if state.other_win.path.as_os_str().is_empty() {
+            let path = state.mid_win.path.clone();
+            state.other_win.change_dir(path, true);
+        }
+
+        let w_pane = (w - 1) / 2;
+        let left = (Pos::new(1, START_TOP), Pos::new(w_pane, h - START_TOP));
+        let right = (
+            Pos::new(1 + w_pane, START_TOP),
+            Pos::new(w - 1 - w_pane, h - START_TOP),
+        );
+        let (active, other) = match state.active_right {
+            true => (right, left),
+            false => (left, right),
+        };
+
+        // El padre y la preview no se ven, pero se ponen en el sitio del
+        // activo: al entrar o salir de un directorio pasan a serlo
+        for win in [
+            &mut state.parent_win,
+            &mut state.mid_win,
+            &mut state.child_win,
+        ] {
+            win.change_dim(active.0, active.1);
+        }
+        state.other_win.change_dim(other.0, other.1);
+    }
+
+    fn display(&self, state: &State) {
+        state.other_win.display();
+        state.display_mid();
+    }
+
+    fn shows(&self, _state: &State, pane: Pane) -> bool {
+        matches!(pane, Pane::Mid | Pane::Other)
+    }
+
+    fn handle_key(&self, state: &mut State, ch: i32) -> bool {
+        match ch {
+            //o
+            111 => state.swap_panes(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn target_dir(&self, state: &State) -> PathBuf {
+        state.other_win.path.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_widths() {
+        let mut c = Columns {
+            parents: 1,
+            ratios: [1, 2, 2],
+            preview: true,
+            maximized: false,
+        };
+        assert_eq!(c.widths(100), [20, 40, 40]);
+
+        c.parents = 2;
+        c.preview = false;
+        assert_eq!(c.widths(99), [24, 24, 51]);
+
+        c.parents = 0;
+        assert_eq!(c.widths(80), [80]);
+
+        c.resize(2, 20);
+        assert_eq!(c.ratios[2], MAX_RATIO);
+        c.resize(0, -5);
+        c.resize(1, 1);
+        assert_eq!(c.ratios, [1, 3, MAX_RATIO]);
+    }
+}
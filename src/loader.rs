@@ -0,0 +1,277 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    vfs::{self, DirEntry},
+    walk,
+};
+
+// Hilos que siempre estan. Si todos estan ocupados (p.ej. colgados en un
+// `read_dir` de un NFS que no responde) se crean mas, que terminan tras
+// `IDLE_TIME` sin trabajo.
+const WORKERS: usize = 4;
+const IDLE_TIME: Duration = Duration::from_secs(30);
+// Las entradas se mandan a trozos para que la lista aparezca enseguida
+// aunque el directorio tenga cientos de miles.
+const BATCH_SIZE: usize = 1024;
+const BATCH_TIME: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum Msg {
+    Entries(Vec<DirEntry>),
+    Done(io::Result<()>),
+}
+
+struct Request {
+    path: PathBuf,
+    // Profundidad y si incluir los ocultos, para leer todo lo de debajo en
+    // una sola lista.
+    flat: Option<(usize, bool)>,
+    cancel: Arc<AtomicBool>,
+    tx: Sender<Msg>,
+}
+
+/// Lectura de un directorio en marcha. Al soltarla se cancela.
+#[derive(Debug)]
+pub struct Load {
+    pub path: PathBuf,
+    rx: Receiver<Msg>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Load {
+    /// Lo que haya llegado desde la ultima vez, sin esperar.
+    pub fn try_iter(&self) -> mpsc::TryIter<'_, Msg> {
+        self.rx.try_iter()
+    }
+}
+
+impl Drop for Load {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Pool {
+    tx: Sender<Request>,
+    rx: Arc<Mutex<Receiver<Request>>>,
+    // Hilos esperando trabajo que no esta ya prometido a una peticion
+    idle: Arc<AtomicUsize>,
+    threads: Arc<AtomicUsize>,
+}
+
+impl Pool {
+    // Manda la peticion a un hilo libre o, si no hay, a uno nuevo
+    fn send(&self, request: Request) {
+        let free = self
+            .idle
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if !free {
+            self.threads.fetch_add(1, Ordering::SeqCst);
+            self.spawn();
+        }
+        // Los hilos fijos no terminan nunca, asi que el envio no puede fallar
+        let _ = self.tx.send(request);
+    }
+
+    fn spawn(&self) {
+        let (rx, idle, threads) = (self.rx.clone(), self.idle.clone(), self.threads.clone());
+        thread::spawn(move || loop {
+            let request = rx.lock().unwrap().recv_timeout(IDLE_TIME);
+            match request {
+                Ok(request) => {
+                    run(request);
+                    idle.fetch_add(1, Ordering::SeqCst);
+                }
+                // Los que sobran se van, pero solo si no hay ya una peticion
+                // contando con ellos
+                Err(RecvTimeoutError::Timeout) => {
+                    if threads.load(Ordering::SeqCst) > WORKERS
+                        && idle
+                            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                            .is_ok()
+                    {
+                        threads.fetch_sub(1, Ordering::SeqCst);
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        });
+    }
+}
+
+fn pool() -> &'static Mutex<Pool> {
+    static POOL: OnceLock<Mutex<Pool>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Request>();
+        let pool = Pool {
+            tx,
+            rx: Arc::new(Mutex::new(rx)),
+            idle: Arc::new(AtomicUsize::new(WORKERS)),
+            threads: Arc::new(AtomicUsize::new(WORKERS)),
+        };
+        for _ in 0..WORKERS {
+            pool.spawn();
+        }
+        Mutex::new(pool)
+    })
+}
+
+/// Empieza a leer `path` en segundo plano.
+pub fn load<P: AsRef<Path>>(path: P) -> Load {
+    start(path.as_ref(), None)
+}
+
+/// Como `load`, pero con todos los ficheros que hay debajo de `path` (ver
+/// `walk::walk`).
+pub fn load_flat<P: AsRef<Path>>(path: P, depth: usize, hidden: bool) -> Load {
+    start(path.as_ref(), Some((depth, hidden)))
+}
+
+fn start(path: &Path, flat: Option<(usize, bool)>) -> Load {
+    let (tx, rx) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let path = path.to_owned();
+
+    let request = Request {
+        path: path.clone(),
+        flat,
+        cancel: cancel.clone(),
+        tx,
+    };
+    pool().lock().unwrap().send(request);
+
+    Load { path, rx, cancel }
+}
+
+fn run(request: Request) {
+    // Puede que ya se haya cancelado mientras esperaba su turno
+    if request.cancel.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut batch = Vec::new();
+    let mut last_send = Instant::now();
+    let mut send = |entry| {
+        if request.cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        batch.push(entry);
+        if batch.len() >= BATCH_SIZE || last_send.elapsed() >= BATCH_TIME {
+            last_send = Instant::now();
+            return request
+                .tx
+                .send(Msg::Entries(std::mem::take(&mut batch)))
+                .is_ok();
+        }
+        true
+    };
+    let result = match request.flat {
+        Some((depth, hidden)) => walk::walk(&request.path, depth, hidden, &mut send),
+        None => vfs::list_with(&request.path, &mut send),
+    };
+
+    if !batch.is_empty() {
+        let _ = request.tx.send(Msg::Entries(batch));
+    }
+    let _ = request.tx.send(Msg::Done(result));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn load_in_batches() {
+        let dir = std::env::temp_dir().join(format!("mag-loader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..BATCH_SIZE + 10 {
+            fs::write(dir.join(i.to_string()), "").unwrap();
+        }
+
+        let listing = load(&dir);
+        let mut entries = 0;
+        let mut batches = 0;
+        loop {
+            match listing.rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Msg::Entries(e) => {
+                    entries += e.len();
+                    batches += 1;
+                }
+                Msg::Done(r) => {
+                    assert!(r.is_ok());
+                    break;
+                }
+            }
+        }
+        assert_eq!(entries, BATCH_SIZE + 10);
+        assert!(batches >= 2);
+
+        let missing = load(dir.join("nope"));
+        match missing.rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Msg::Done(r) => assert!(r.is_err()),
+            m => panic!("{:?}", m),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Un sistema de ficheros que no responde, como un NFS caido
+    struct Stalled(Arc<AtomicBool>);
+
+    impl vfs::Vfs for Stalled {
+        fn list(&self, _path: &Path) -> io::Result<Vec<DirEntry>> {
+            while self.0.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Ok(Vec::new())
+        }
+
+        fn stat(&self, _path: &Path) -> io::Result<vfs::Stat> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+
+        fn open(&self, _path: &Path) -> io::Result<Box<dyn vfs::VfsFile>> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn stalled_loads_dont_block_others() {
+        let stalled = PathBuf::from("/mag-stalled-test");
+        let stuck = Arc::new(AtomicBool::new(true));
+        vfs::mount(&stalled, Arc::new(Stalled(stuck.clone())));
+
+        // Todos los hilos fijos se quedan colgados
+        let hung: Vec<Load> = (0..WORKERS + 1).map(|_| load(&stalled)).collect();
+        let healthy = load(std::env::temp_dir());
+        loop {
+            let msg = healthy.rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            if let Msg::Done(r) = msg {
+                assert!(r.is_ok());
+                break;
+            }
+        }
+
+        stuck.store(false, Ordering::SeqCst);
+        for l in &hung {
+            assert!(matches!(
+                l.rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+                Msg::Done(Ok(()))
+            ));
+        }
+        vfs::unmount(&stalled);
+    }
+}
@@ -1,11 +1,37 @@
+use config::Config;
 use state::State;
 
+pub mod archive;
+pub mod bookmarks;
+pub mod breadcrumb;
+pub mod cache;
+pub mod complete;
+pub mod config;
 pub mod filesys;
+pub mod frecency;
+pub mod history;
+pub mod jobs;
+pub mod layout;
+pub mod loader;
+pub mod memfs;
+pub mod menu;
+pub mod mouse;
+pub mod ops;
+pub mod pager;
 pub mod pos;
+pub mod prompt;
 pub mod state;
+pub mod tab;
+pub mod text;
+pub mod tree;
 pub mod ui;
+pub mod vfs;
+pub mod walk;
+pub mod watcher;
 
 fn main() {
+    config::init(Config::load());
+
     /*
         let _ = State::new("/home/mikel/Escritorio/ruby/rb/rb/")
             .unwrap()
@@ -13,7 +39,7 @@ fn main() {
             .unwrap()
             .exit();
     */
-    let _ = State::new("/home/mikel/Escritorio/")
+    State::new("/home/mikel/Escritorio/")
         .expect("ERROR CREATING STATE IN MAIN")
         .update()
         .expect("ERROR IN UPDATE")
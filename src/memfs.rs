@@ -0,0 +1,183 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::OsString,
+    io,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use crate::vfs::{DirEntry, Kind, MemFile, Stat, Vfs, VfsFile};
+
+// Niveles de enlaces que se siguen antes de dar el error de bucle.
+const MAX_LINK_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File(Arc<Vec<u8>>),
+    Link(PathBuf),
+}
+
+/// Sistema de ficheros en memoria. Sirve para los tests y como ejemplo de
+/// como montar un `Vfs` propio con `vfs::mount`.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: RwLock<BTreeMap<PathBuf, (Node, SystemTime)>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_dir<P: AsRef<Path>>(&self, path: P) {
+        self.insert(path.as_ref(), Node::Dir);
+    }
+
+    pub fn add_file<P: AsRef<Path>, B: Into<Vec<u8>>>(&self, path: P, content: B) {
+        self.insert(path.as_ref(), Node::File(Arc::new(content.into())));
+    }
+
+    pub fn add_symlink<P: AsRef<Path>, T: AsRef<Path>>(&self, path: P, target: T) {
+        self.insert(path.as_ref(), Node::Link(target.as_ref().to_owned()));
+    }
+
+    // Crea tambien los directorios intermedios, como `mkdir -p`
+    fn insert(&self, path: &Path, node: Node) {
+        let mut nodes = self.nodes.write().unwrap();
+        for a in path.ancestors().skip(1) {
+            nodes
+                .entry(a.to_owned())
+                .or_insert((Node::Dir, SystemTime::now()));
+        }
+        nodes.insert(path.to_owned(), (node, SystemTime::now()));
+    }
+
+    // Nodo final tras seguir los enlaces.
+    fn follow(&self, path: &Path) -> io::Result<(Node, SystemTime)> {
+        let path = self.resolve(path)?;
+        match self.nodes.read().unwrap().get(&path) {
+            Some(node) => Ok(node.clone()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    // La ruta real de `path`: se siguen los enlaces de todos sus componentes,
+    // no solo del ultimo.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let nodes = self.nodes.read().unwrap();
+        let mut pending: VecDeque<OsString> = components(path);
+        let mut resolved = PathBuf::new();
+        let mut links = 0;
+
+        while let Some(c) = pending.pop_front() {
+            if c == ".." {
+                resolved.pop();
+                continue;
+            }
+            resolved.push(&c);
+            let Some((Node::Link(target), _)) = nodes.get(&resolved) else {
+                continue;
+            };
+
+            links += 1;
+            if links > MAX_LINK_DEPTH {
+                return Err(io::Error::other("too many levels of symbolic links"));
+            }
+            resolved.pop();
+            // Lo que queda va detras del destino
+            let mut next = components(&resolved.join(target));
+            next.extend(pending);
+            pending = next;
+            resolved = PathBuf::new();
+        }
+        Ok(resolved)
+    }
+
+    // `path` con los enlaces de su directorio resueltos, pero no el ultimo
+    // componente: para mirar si es un enlace
+    fn real_parent(&self, path: &Path) -> PathBuf {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => match self.resolve(parent) {
+                Ok(parent) => parent.join(name),
+                Err(_) => path.to_owned(),
+            },
+            _ => path.to_owned(),
+        }
+    }
+}
+
+fn components(path: &Path) -> VecDeque<OsString> {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_owned())
+        .collect()
+}
+
+impl Vfs for MemoryFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        if !matches!(self.follow(path)?.0, Node::Dir) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a directory"));
+        }
+
+        // Los hijos se buscan en el directorio real y se devuelven bajo la
+        // ruta pedida, aunque se llegue por un enlace
+        let real = self.resolve(path)?;
+        let children: Vec<PathBuf> = self
+            .nodes
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|p| p.parent() == Some(&real))
+            .filter_map(|p| p.file_name().map(|name| path.join(name)))
+            .collect();
+
+        Ok(children
+            .into_iter()
+            .filter_map(|path| {
+                let stat = self.stat(&path).ok()?;
+                Some(DirEntry::new(path, stat))
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let link = matches!(
+            self.nodes.read().unwrap().get(&self.real_parent(path)),
+            Some((Node::Link(_), _))
+        );
+        let (node, modified) = self.follow(path)?;
+
+        let (kind, size) = match node {
+            Node::Dir => (Kind::Dir, 0),
+            Node::File(data) => (Kind::File, data.len() as u64),
+            Node::Link(_) => unreachable!(),
+        };
+
+        Ok(Stat {
+            kind,
+            size,
+            modified: Some(modified),
+            link,
+        })
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        match self.follow(path)?.0 {
+            Node::File(data) => Ok(Box::new(MemFile(data))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "is a directory",
+            )),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.nodes.read().unwrap().get(&self.real_parent(path)) {
+            Some((Node::Link(target), _)) => Ok(target.clone()),
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "not a symlink")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
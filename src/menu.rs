@@ -0,0 +1,91 @@
+use std::{thread::sleep, time::Duration};
+
+use ncurses::*;
+
+use crate::text;
+
+const ESC: i32 = 27;
+const ENTER: i32 = 10;
+
+/// Lista a pantalla completa para elegir una de `items`, empezando con el
+/// cursor en `idx`. Devuelve la opcion elegida y la tecla con la que se ha
+/// elegido: `'\n'` para Enter o cualquiera de las de `actions` (p.ej. `"d"`
+/// para borrar). Con `q` o Esc devuelve `None`.
+pub fn pick(title: &str, items: &[String], idx: usize, actions: &str) -> Option<(usize, char)> {
+    let was_nodelay = is_nodelay(stdscr());
+    nodelay(stdscr(), true);
+
+    let mut idx = idx.min(items.len().saturating_sub(1));
+    let mut top = 0;
+
+    let result = loop {
+        let rows = (LINES() - 2).max(1) as usize;
+        if idx < top {
+            top = idx;
+        } else if idx >= top + rows {
+            top = idx + 1 - rows;
+        }
+        display(title, items, idx, top, rows);
+
+        let ch = getch();
+        match ch {
+            ERR => sleep(Duration::from_millis(10)),
+            //q
+            113 | ESC => break None,
+            ENTER | KEY_ENTER if !items.is_empty() => break Some((idx, '\n')),
+            //j
+            106 | KEY_DOWN => idx = (idx + 1).min(items.len().saturating_sub(1)),
+            //k
+            107 | KEY_UP => idx = idx.saturating_sub(1),
+            //Ctrl-F
+            6 | KEY_NPAGE => idx = (idx + rows).min(items.len().saturating_sub(1)),
+            //Ctrl-B
+            2 | KEY_PPAGE => idx = idx.saturating_sub(rows),
+            //g
+            103 | KEY_HOME => idx = 0,
+            //G
+            71 | KEY_END => idx = items.len().saturating_sub(1),
+            ch if !items.is_empty() => match char::from_u32(ch as u32) {
+                Some(c) if actions.contains(c) => break Some((idx, c)),
+                _ => {}
+            },
+            _ => {}
+        }
+    };
+
+    nodelay(stdscr(), was_nodelay);
+    clear();
+    refresh();
+    result
+}
+
+fn display(title: &str, items: &[String], idx: usize, top: usize, rows: usize) {
+    erase();
+    let width = COLS().max(0) as usize;
+
+    attron(A_BOLD());
+    mvaddstr(0, 1, &text::clip(title, width.saturating_sub(2)));
+    attroff(A_BOLD());
+
+    if items.is_empty() {
+        attron(A_DIM());
+        mvaddstr(1, 2, "(empty)");
+        attroff(A_DIM());
+    }
+
+    for (i, item) in items.iter().enumerate().skip(top).take(rows) {
+        let line = text::pad_right(
+            &text::clip(item, width.saturating_sub(3)),
+            width.saturating_sub(3),
+        );
+        let attr = match i == idx {
+            true => COLOR_PAIR(5) | A_BOLD(),
+            false => A_NORMAL(),
+        };
+        attron(attr);
+        mvaddstr((i - top) as i32 + 1, 2, &line);
+        attroff(attr);
+    }
+
+    refresh();
+}
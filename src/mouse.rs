@@ -0,0 +1,78 @@
+use ncurses::*;
+
+/// Lineas que se mueve una lista o la preview con cada paso de la rueda.
+pub const WHEEL_STEP: isize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Click,
+    DoubleClick,
+    WheelUp,
+    WheelDown,
+}
+
+/// Lo que ha hecho el raton y donde, en coordenadas de pantalla.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub action: Action,
+    pub y: i32,
+    pub x: i32,
+}
+
+/// Pide a ncurses los eventos del raton; llegan a `getch` como `KEY_MOUSE`.
+pub fn init() {
+    let mask = BUTTON1_CLICKED | BUTTON1_DOUBLE_CLICKED | BUTTON4_PRESSED | BUTTON5_PRESSED;
+    mousemask(mask as mmask_t, None);
+}
+
+/// El evento que ha provocado el ultimo `KEY_MOUSE`, si es de los que
+/// interesan.
+pub fn read() -> Option<Event> {
+    let mut event = MEVENT {
+        id: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+        bstate: 0,
+    };
+    if getmouse(&mut event) != OK {
+        return None;
+    }
+    action(event.bstate).map(|action| Event {
+        action,
+        y: event.y,
+        x: event.x,
+    })
+}
+
+fn action(bstate: mmask_t) -> Option<Action> {
+    let has = |mask: i32| bstate & mask as mmask_t != 0;
+    if has(BUTTON1_DOUBLE_CLICKED) {
+        Some(Action::DoubleClick)
+    } else if has(BUTTON1_CLICKED) {
+        Some(Action::Click)
+    } else if has(BUTTON4_PRESSED) {
+        Some(Action::WheelUp)
+    } else if has(BUTTON5_PRESSED) {
+        Some(Action::WheelDown)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions() {
+        let m = |mask: i32| mask as mmask_t;
+        assert_eq!(action(m(BUTTON1_CLICKED)), Some(Action::Click));
+        assert_eq!(
+            action(m(BUTTON1_DOUBLE_CLICKED | BUTTON_SHIFT)),
+            Some(Action::DoubleClick)
+        );
+        assert_eq!(action(m(BUTTON5_PRESSED)), Some(Action::WheelDown));
+        assert_eq!(action(m(BUTTON3_CLICKED)), None);
+    }
+}
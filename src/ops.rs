@@ -0,0 +1,394 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+use crate::{jobs::Progress, vfs};
+
+/// Que hacer cuando el destino de una operacion ya existe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    Overwrite,
+    Skip,
+    /// Escribir al lado con otro nombre: `foto (1).jpg`.
+    Rename,
+}
+
+impl Conflict {
+    /// A partir de la tecla pulsada en la pregunta `[o]verwrite [s]kip
+    /// [r]ename`.
+    pub fn from_key(c: char) -> Option<Self> {
+        match c {
+            'o' => Some(Conflict::Overwrite),
+            's' => Some(Conflict::Skip),
+            'r' => Some(Conflict::Rename),
+            _ => None,
+        }
+    }
+}
+
+/// Primer nombre libre del estilo `nombre (n).ext` junto a `path`.
+pub fn unique_name<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if path.symlink_metadata().is_err() {
+        return path.to_owned();
+    }
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // La extension de `a.tar.gz` es `.tar.gz`, no `.gz`
+    let split = match name.find('.') {
+        Some(0) | None => name.len(),
+        Some(i) => i,
+    };
+    let (stem, ext) = name.split_at(split);
+
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| p.symlink_metadata().is_err())
+        .unwrap()
+}
+
+#[derive(Debug, Default)]
+pub struct CopyReport {
+    pub files: usize,
+    /// Ya existian y se ha elegido `Conflict::Skip`.
+    pub skipped: usize,
+    /// Viene de `move_to` y no de `copy`.
+    pub moved: bool,
+}
+
+impl CopyReport {
+    pub fn summary(&self) -> String {
+        let verb = if self.moved { "moved" } else { "copied" };
+        let mut s = format!("{} files {}", self.files, verb);
+        if self.skipped > 0 {
+            s.push_str(&format!(", {} skipped", self.skipped));
+        }
+        s
+    }
+}
+
+/// Copia `sources` dentro del directorio `dest`. Pueden venir del disco o de
+/// dentro de un archivo comprimido; los directorios se copian enteros y los
+/// enlaces simbolicos como enlaces, aunque esten rotos. `conflict` decide que hacer con los que
+/// ya existen en `dest`; un directorio que ya existe con `Overwrite` se
+/// mezcla con el nuevo.
+pub fn copy(
+    sources: &[PathBuf],
+    dest: &Path,
+    conflict: Conflict,
+    progress: &Progress,
+) -> io::Result<CopyReport> {
+    check_not_inside(sources, dest)?;
+    progress.set_total(sources.iter().map(|s| total_size(s)).sum());
+
+    let mut report = CopyReport::default();
+    for src in sources {
+        if let Some(target) = target_for(src, dest, conflict, &mut report)? {
+            copy_entry(src, &target, progress, &mut report)?;
+        }
+    }
+    Ok(report)
+}
+
+/// Como `copy`, pero borrando los originales. Dentro del mismo sistema de
+/// ficheros solo se renombra; si no, se copia y luego se borra.
+pub fn move_to(
+    sources: &[PathBuf],
+    dest: &Path,
+    conflict: Conflict,
+    progress: &Progress,
+) -> io::Result<CopyReport> {
+    check_not_inside(sources, dest)?;
+    if sources.iter().any(|s| vfs::is_virtual(s)) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "read-only: inside an archive",
+        ));
+    }
+    progress.set_total(sources.iter().map(|s| total_size(s)).sum());
+
+    let mut report = CopyReport {
+        moved: true,
+        ..CopyReport::default()
+    };
+    for src in sources {
+        let Some(target) = target_for(src, dest, conflict, &mut report)? else {
+            continue;
+        };
+        let size = total_size(src);
+        // Un directorio que ya existe se mezcla, y entre sistemas de ficheros
+        // no se puede renombrar: en los dos casos se copia y se borra. Los
+        // demas errores se devuelven tal cual
+        let merge = target.symlink_metadata().is_ok_and(|m| m.is_dir());
+        let renamed = match merge {
+            true => false,
+            false => match fs::rename(src, &target) {
+                Ok(()) => true,
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => false,
+                Err(e) => return Err(e),
+            },
+        };
+        if renamed {
+            progress.add(size);
+            report.files += 1;
+        } else {
+            copy_entry(src, &target, progress, &mut report)?;
+            match src.symlink_metadata()?.is_dir() {
+                true => fs::remove_dir_all(src)?,
+                false => fs::remove_file(src)?,
+            }
+        }
+    }
+    Ok(report)
+}
+
+// Se comparan las rutas reales: `dest` puede llegar a un origen por un
+// enlace o con `..`
+fn check_not_inside(sources: &[PathBuf], dest: &Path) -> io::Result<()> {
+    let real_dest = fs::canonicalize(dest).unwrap_or_else(|_| dest.to_owned());
+    match sources
+        .iter()
+        .find(|s| dest.starts_with(s) || real_dest.starts_with(real_path(s)))
+    {
+        Some(src) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't copy {} into itself", src.display()),
+        )),
+        None => Ok(()),
+    }
+}
+
+// Como `fs::canonicalize` pero sin seguir el ultimo componente: un enlace
+// se copia como enlace, no lo que hay detras. Lo que no esta en el disco se
+// deja como esta.
+fn real_path(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => match fs::canonicalize(parent) {
+            Ok(parent) => parent.join(name),
+            Err(_) => path.to_owned(),
+        },
+        _ => path.to_owned(),
+    }
+}
+
+// Donde escribir `src` dentro de `dest` segun `conflict`, o `None` si hay
+// que saltarlo
+fn target_for(
+    src: &Path,
+    dest: &Path,
+    conflict: Conflict,
+    report: &mut CopyReport,
+) -> io::Result<Option<PathBuf>> {
+    let Some(name) = src.file_name() else {
+        return Ok(None);
+    };
+    let target = dest.join(name);
+    let Ok(existing) = target.symlink_metadata() else {
+        return Ok(Some(target));
+    };
+
+    match conflict {
+        Conflict::Rename => return Ok(Some(unique_name(&target))),
+        // Pegar en el mismo directorio sin cambiar el nombre no hace nada
+        Conflict::Overwrite if target == src => {}
+        Conflict::Overwrite if existing.is_dir() && is_dir(src) => return Ok(Some(target)),
+        Conflict::Overwrite if !existing.is_dir() => {
+            fs::remove_file(&target)?;
+            return Ok(Some(target));
+        }
+        _ => {}
+    }
+    report.skipped += 1;
+    Ok(None)
+}
+
+fn copy_entry(
+    src: &Path,
+    target: &Path,
+    progress: &Progress,
+    report: &mut CopyReport,
+) -> io::Result<()> {
+    // Dentro de los archivos comprimidos no hay enlaces
+    if !vfs::is_virtual(src) && src.symlink_metadata()?.is_symlink() {
+        if target.symlink_metadata().is_ok() {
+            fs::remove_file(target)?;
+        }
+        symlink(fs::read_link(src)?, target)?;
+        report.files += 1;
+        return Ok(());
+    }
+
+    if is_dir(src) {
+        fs::create_dir_all(target)?;
+        for child in children(src)? {
+            let inner = target.join(child.file_name().unwrap_or_default());
+            // Al mezclar con un directorio existente se sobreescribe
+            if !is_dir(&child) && inner.is_file() {
+                fs::remove_file(&inner)?;
+            }
+            copy_entry(&child, &inner, progress, report)?;
+        }
+        return Ok(());
+    }
+
+    let file = vfs::open(src)?;
+    let mut out = File::create(target)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = file.read_at(&mut buf, offset)?;
+        if n == 0 {
+            break;
+        }
+        out.write_all(&buf[..n])?;
+        offset += n as u64;
+        progress.add(n as u64);
+    }
+    if !vfs::is_virtual(src) {
+        fs::set_permissions(target, src.metadata()?.permissions())?;
+    }
+    report.files += 1;
+    Ok(())
+}
+
+// Lo que hay dentro de `dir`. En el disco no se usa `vfs::list`, que se
+// salta los enlaces rotos: hay que copiarlos tambien, y al mover se borrarian
+fn children(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if vfs::is_virtual(dir) {
+        return Ok(vfs::list(dir)?.into_iter().map(|e| e.path).collect());
+    }
+    fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect()
+}
+
+fn is_dir(path: &Path) -> bool {
+    vfs::stat(path).is_ok_and(|s| s.is_dir())
+}
+
+fn total_size(path: &Path) -> u64 {
+    match vfs::stat(path) {
+        Ok(s) if s.is_dir() && !s.link => vfs::list(path)
+            .unwrap_or_default()
+            .iter()
+            .map(|e| total_size(&e.path))
+            .sum(),
+        Ok(s) if s.link => 0,
+        Ok(s) => s.size,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn unique() {
+        let dir = std::env::temp_dir().join(format!("mag-ops-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(unique_name(dir.join("a.tar.gz")), dir.join("a.tar.gz"));
+        fs::write(dir.join("a.tar.gz"), "").unwrap();
+        assert_eq!(unique_name(dir.join("a.tar.gz")), dir.join("a (1).tar.gz"));
+        fs::write(dir.join("a (1).tar.gz"), "").unwrap();
+        assert_eq!(unique_name(dir.join("a.tar.gz")), dir.join("a (2).tar.gz"));
+
+        fs::write(dir.join(".bashrc"), "").unwrap();
+        assert_eq!(unique_name(dir.join(".bashrc")), dir.join(".bashrc (1)"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_tree() {
+        let dir = std::env::temp_dir().join(format!("mag-copy-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src/sub")).unwrap();
+        fs::create_dir_all(dir.join("dest")).unwrap();
+        fs::write(dir.join("src/a.txt"), "aaa").unwrap();
+        fs::write(dir.join("src/sub/b.txt"), "bb").unwrap();
+        symlink("a.txt", dir.join("src/link")).unwrap();
+        symlink("no-existe", dir.join("src/roto")).unwrap();
+
+        let p = Progress::default();
+        let r = copy(&[dir.join("src")], &dir.join("dest"), Conflict::Skip, &p).unwrap();
+        assert_eq!(r.files, 4);
+        assert_eq!(p.status().done, 5);
+        assert_eq!(fs::read(dir.join("dest/src/sub/b.txt")).unwrap(), b"bb");
+        assert!(dir
+            .join("dest/src/link")
+            .symlink_metadata()
+            .unwrap()
+            .is_symlink());
+        // Los enlaces rotos tambien se copian
+        assert_eq!(
+            fs::read_link(dir.join("dest/src/roto")).unwrap(),
+            PathBuf::from("no-existe")
+        );
+
+        let r = copy(&[dir.join("src")], &dir.join("dest"), Conflict::Skip, &p).unwrap();
+        assert_eq!((r.files, r.skipped), (0, 1));
+        copy(&[dir.join("src")], &dir.join("dest"), Conflict::Rename, &p).unwrap();
+        assert!(dir.join("dest/src (1)/a.txt").exists());
+
+        fs::write(dir.join("src/a.txt"), "nuevo").unwrap();
+        copy(
+            &[dir.join("src")],
+            &dir.join("dest"),
+            Conflict::Overwrite,
+            &p,
+        )
+        .unwrap();
+        assert_eq!(fs::read(dir.join("dest/src/a.txt")).unwrap(), b"nuevo");
+
+        assert!(copy(&[dir.join("src")], &dir.join("src/sub"), Conflict::Skip, &p).is_err());
+        // Tambien cuando se llega al destino por un enlace o con `..`
+        symlink("src/sub", dir.join("atajo")).unwrap();
+        assert!(copy(&[dir.join("src")], &dir.join("atajo"), Conflict::Skip, &p).is_err());
+        let dotdot = dir.join("dest/../src/sub");
+        assert!(copy(&[dir.join("src")], &dotdot, Conflict::Skip, &p).is_err());
+        fs::remove_file(dir.join("atajo")).unwrap();
+
+        let r = move_to(&[dir.join("dest/src (1)")], &dir, Conflict::Skip, &p).unwrap();
+        assert_eq!(r.summary(), "1 files moved");
+        assert!(dir.join("src (1)/sub/b.txt").exists());
+        assert!(!dir.join("dest/src (1)").exists());
+        let r = move_to(
+            &[dir.join("src/a.txt")],
+            &dir.join("src"),
+            Conflict::Overwrite,
+            &p,
+        );
+        assert_eq!(r.unwrap().skipped, 1);
+        assert!(dir.join("src/a.txt").exists());
+
+        // Un directorio que ya existe se mezcla; otros errores no se tapan
+        // copiando
+        fs::create_dir_all(dir.join("otro/sub")).unwrap();
+        fs::write(dir.join("otro/sub/c.txt"), "c").unwrap();
+        symlink("no-existe", dir.join("otro/sub/roto")).unwrap();
+        move_to(
+            &[dir.join("otro/sub")],
+            &dir.join("src"),
+            Conflict::Overwrite,
+            &p,
+        )
+        .unwrap();
+        assert!(dir.join("src/sub/b.txt").exists());
+        assert!(dir.join("src/sub/c.txt").exists());
+        assert_eq!(
+            fs::read_link(dir.join("src/sub/roto")).unwrap(),
+            PathBuf::from("no-existe")
+        );
+        assert!(!dir.join("otro/sub").exists());
+        let missing = move_to(&[dir.join("nada")], &dir.join("src"), Conflict::Skip, &p);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,619 @@
+use std::{
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use ncurses::*;
+
+use crate::{
+    filesys, prompt, text,
+    vfs::{self, VfsFile},
+};
+
+const READ_CHUNK: usize = 64 * 1024;
+// Igual que en la preview, una linea enorme solo se lee hasta aqui.
+const MAX_LINE_BYTES: u64 = 16 * 1024;
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+// Bytes del final de lo indexado que se guardan para notar si el fichero se
+// ha reescrito por debajo.
+const TAIL_BYTES: usize = 64;
+
+/// Indice de los comienzos de linea de un fichero. Se construye segun se
+/// necesita, asi que abrir un fichero de varios GB solo lee lo que se ve.
+pub struct LineIndex {
+    path: PathBuf,
+    file: Box<dyn VfsFile>,
+    // Inodo del fichero abierto, para notar si lo han cambiado por otro al
+    // rotarlo. `None` dentro de un archivo comprimido.
+    inode: Option<u64>,
+    starts: Vec<u64>,
+    scanned: u64,
+    complete: bool,
+    // Los ultimos bytes indexados. Si ya no estan es que el fichero se ha
+    // truncado y ha vuelto a crecer entre dos miradas (copytruncate).
+    tail: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl LineIndex {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        Ok(Self {
+            file: vfs::open(&path)?,
+            inode: inode(&path),
+            path,
+            starts: vec![0],
+            scanned: 0,
+            complete: false,
+            tail: Vec::new(),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Escanea el fichero hasta conocer la linea `line` completa.
+    pub fn index_to(&mut self, line: usize) {
+        if self.complete || self.starts.len() > line + 1 {
+            return;
+        }
+        self.buf.resize(READ_CHUNK, 0);
+
+        while !self.complete && self.starts.len() <= line + 1 {
+            let read = match self.file.read_at(&mut self.buf, self.scanned) {
+                Ok(0) | Err(_) => {
+                    self.complete = true;
+                    break;
+                }
+                Ok(read) => read,
+            };
+
+            let chunk = &self.buf[..read];
+            for (i, b) in chunk.iter().enumerate() {
+                if *b == b'\n' {
+                    self.starts.push(self.scanned + i as u64 + 1);
+                }
+            }
+            self.scanned += read as u64;
+
+            self.tail
+                .extend_from_slice(&chunk[read.saturating_sub(TAIL_BYTES)..]);
+            let extra = self.tail.len().saturating_sub(TAIL_BYTES);
+            self.tail.drain(..extra);
+        }
+    }
+
+    pub fn index_all(&mut self) {
+        self.index_to(usize::MAX - 1);
+    }
+
+    /// Lineas conocidas hasta ahora. Solo es el total si `is_complete`.
+    pub fn len(&self) -> usize {
+        let last = *self.starts.last().unwrap();
+        if self.complete && last < self.scanned {
+            self.starts.len()
+        } else {
+            self.starts.len() - 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn line(&mut self, i: usize) -> Option<String> {
+        self.index_to(i);
+        if i >= self.len() {
+            return None;
+        }
+
+        let start = self.starts[i];
+        let end = match self.starts.get(i + 1) {
+            Some(next) => next - 1,
+            None => self.scanned,
+        };
+        let len = (end - start).min(MAX_LINE_BYTES) as usize;
+
+        let mut buf = vec![0u8; len];
+        let read = self.file.read_at(&mut buf, start).unwrap_or(0);
+        buf.truncate(read);
+        // Si se ha cortado, que no sea a mitad de un caracter
+        if (len as u64) < end - start {
+            buf.truncate(filesys::char_boundary(&buf));
+        }
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+
+        Some(String::from_utf8_lossy(&buf).to_string())
+    }
+
+    /// Mira si el fichero ha cambiado de tamaño. Si ha crecido se seguira
+    /// indexando desde donde se quedo; si ha encogido (truncado o rotado) se
+    /// empieza de cero. Devuelve `true` si hay algo nuevo que mostrar.
+    pub fn refresh(&mut self) -> bool {
+        // Rotado con rename: se abre el fichero nuevo
+        let inode = inode(&self.path);
+        if inode != self.inode && inode.is_some() {
+            if let Ok(file) = vfs::open(&self.path) {
+                self.file = file;
+                self.inode = inode;
+                self.reset();
+                return true;
+            }
+        }
+
+        let size = match self.file.size() {
+            Ok(len) => len,
+            Err(_) => return false,
+        };
+
+        if size < self.scanned || !self.tail_unchanged() {
+            self.reset();
+            true
+        } else if size > self.scanned {
+            self.complete = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tail_unchanged(&self) -> bool {
+        let mut buf = vec![0u8; self.tail.len()];
+        let start = self.scanned - self.tail.len() as u64;
+        match self.file.read_at(&mut buf, start) {
+            Ok(read) => buf[..read] == self.tail[..],
+            Err(_) => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.starts = vec![0];
+        self.scanned = 0;
+        self.complete = false;
+        self.tail.clear();
+    }
+}
+
+fn inode(path: &Path) -> Option<u64> {
+    match vfs::is_virtual(path) {
+        true => None,
+        false => fs::metadata(path).ok().map(|m| m.ino()),
+    }
+}
+
+/// Visor a pantalla completa, para echar un vistazo a un fichero sin salir
+/// de mag.
+pub struct Pager {
+    path: PathBuf,
+    index: LineIndex,
+    top: usize,
+    wrap: bool,
+    numbers: bool,
+    follow: bool,
+    search: Option<String>,
+    message: Option<String>,
+}
+
+impl Pager {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let index = LineIndex::open(&path)?;
+
+        Ok(Self {
+            path,
+            index,
+            top: 0,
+            wrap: false,
+            numbers: true,
+            follow: false,
+            search: None,
+            message: None,
+        })
+    }
+
+    pub fn run(&mut self) {
+        let was_nodelay = is_nodelay(stdscr());
+        nodelay(stdscr(), true);
+        clear();
+
+        let mut last_check = Instant::now();
+        self.display();
+
+        loop {
+            let ch = getch();
+            if ch == ERR {
+                if self.follow && last_check.elapsed() >= FOLLOW_INTERVAL {
+                    last_check = Instant::now();
+                    if self.index.refresh() {
+                        self.go_bottom();
+                        self.display();
+                    }
+                }
+                sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            self.message = None;
+            let rows = Self::rows();
+
+            match ch {
+                //q
+                113 | 27 => break,
+                //j
+                106 | KEY_DOWN | 10 => self.scroll(1),
+                //k
+                107 | KEY_UP => {
+                    self.follow = false;
+                    self.scroll(-1);
+                }
+                //space, Ctrl-F
+                32 | 6 | KEY_NPAGE => self.scroll(rows as isize),
+                //b, Ctrl-B
+                98 | 2 | KEY_PPAGE => {
+                    self.follow = false;
+                    self.scroll(-(rows as isize));
+                }
+                //g
+                103 | KEY_HOME => {
+                    self.follow = false;
+                    self.top = 0;
+                }
+                //G
+                71 | KEY_END => self.go_bottom(),
+                //w
+                119 => self.wrap = !self.wrap,
+                //#
+                35 => self.numbers = !self.numbers,
+                //F
+                70 => {
+                    self.follow = !self.follow;
+                    if self.follow {
+                        self.index.refresh();
+                        self.go_bottom();
+                    }
+                }
+                // /
+                47 => {
+                    if let Some(q) = prompt::read_line(stdscr(), LINES() - 1, "/", "") {
+                        if !q.is_empty() {
+                            self.search = Some(q);
+                            self.find(true, self.top);
+                        }
+                    }
+                }
+                //n
+                110 => self.find(true, self.top + 1),
+                //N
+                78 => self.find(false, self.top.saturating_sub(1)),
+                //:
+                58 => {
+                    if let Some(n) = prompt::read_line(stdscr(), LINES() - 1, "line: ", "") {
+                        match n.trim().parse::<usize>() {
+                            Ok(n) => self.goto_line(n.saturating_sub(1)),
+                            Err(_) => self.message = Some(format!("invalid line: {}", n)),
+                        }
+                    }
+                }
+                // Se repinta con el tamaño nuevo
+                KEY_RESIZE => {}
+                _ => continue,
+            }
+
+            self.display();
+        }
+
+        nodelay(stdscr(), was_nodelay);
+        clear();
+        refresh();
+    }
+
+    fn rows() -> usize {
+        (LINES() - 1).max(1) as usize
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let target = self.top.saturating_add_signed(delta);
+        self.goto_line(target);
+    }
+
+    fn goto_line(&mut self, line: usize) {
+        self.index.index_to(line);
+        let last = self.index.len().saturating_sub(1);
+        self.top = line.min(last);
+    }
+
+    // Pone el final del fichero en la ultima fila de la pantalla.
+    fn go_bottom(&mut self) {
+        self.index.index_all();
+        let rows = Self::rows();
+        let width = self.text_width();
+
+        let mut used = 0;
+        let mut top = self.index.len();
+        while top > 0 {
+            let h = match self.wrap {
+                true => self
+                    .line_text(top - 1)
+                    .map_or(1, |l| text::wrap(&l, width).len()),
+                false => 1,
+            };
+            if used + h > rows {
+                break;
+            }
+            used += h;
+            top -= 1;
+        }
+        self.top = top;
+    }
+
+    fn find(&mut self, forward: bool, from: usize) {
+        let Some(query) = self.search.clone() else {
+            self.message = Some("no previous search".to_string());
+            return;
+        };
+
+        let mut i = from;
+        loop {
+            match self.line_text(i) {
+                Some(line) if !matches(&line, &query).is_empty() => {
+                    self.top = i;
+                    return;
+                }
+                Some(_) => {}
+                None if forward => break,
+                None => {}
+            }
+
+            if forward {
+                i += 1;
+            } else if i == 0 {
+                break;
+            } else {
+                i -= 1;
+            }
+        }
+
+        self.message = Some(format!("pattern not found: {}", query));
+    }
+
+    fn line_text(&mut self, i: usize) -> Option<String> {
+        self.index.line(i).map(|l| text::sanitize_line(&l))
+    }
+
+    fn gutter(&self) -> usize {
+        match self.numbers {
+            true => {
+                (self.top + Self::rows())
+                    .max(self.index.len())
+                    .to_string()
+                    .len()
+                    + 1
+            }
+            false => 0,
+        }
+    }
+
+    fn text_width(&self) -> usize {
+        (COLS() as usize).saturating_sub(self.gutter()).max(1)
+    }
+
+    fn display(&mut self) {
+        erase();
+        let rows = Self::rows();
+        let gutter = self.gutter();
+        let width = self.text_width();
+
+        let mut row = 0;
+        let mut i = self.top;
+        while row < rows {
+            let Some(line) = self.line_text(i) else {
+                mvaddstr(row as i32, 0, "~");
+                row += 1;
+                continue;
+            };
+
+            let highlight = match &self.search {
+                Some(q) => matches(&line, q),
+                None => Vec::new(),
+            };
+            let segments = match self.wrap {
+                true => text::wrap(&line, width),
+                false => vec![text::clip(&line, width)],
+            };
+
+            let mut offset = 0;
+            for (n, seg) in segments.iter().enumerate() {
+                if row >= rows {
+                    break;
+                }
+                if self.numbers && n == 0 {
+                    attron(COLOR_PAIR(3));
+                    mvaddstr(row as i32, 0, &format!("{:>w$} ", i + 1, w = gutter - 1));
+                    attroff(COLOR_PAIR(3));
+                }
+                draw_highlighted(row as i32, gutter as i32, seg, offset, &highlight);
+                offset += seg.chars().count();
+                row += 1;
+            }
+            i += 1;
+        }
+
+        self.display_status();
+        refresh();
+    }
+
+    fn display_status(&self) {
+        let total = match self.index.is_complete() {
+            true => self.index.len().to_string(),
+            false => "?".to_string(),
+        };
+
+        let mut status = format!(
+            " {}  {}/{}",
+            text::sanitize_os_str(self.path.as_os_str()),
+            self.top + 1,
+            total
+        );
+        if self.wrap {
+            status.push_str("  [wrap]");
+        }
+        if self.follow {
+            status.push_str("  [follow]");
+        }
+        if let Some(q) = &self.search {
+            status.push_str(&format!("  /{}", text::sanitize(q)));
+        }
+        if let Some(m) = &self.message {
+            status.push_str(&format!("  {}", m));
+        }
+
+        let width = COLS().max(0) as usize;
+        attron(A_REVERSE());
+        mvaddstr(
+            LINES() - 1,
+            0,
+            &text::pad_right(&text::clip(&status, width), width),
+        );
+        attroff(A_REVERSE());
+    }
+}
+
+/// Marca los caracteres de `line` que forman parte de alguna coincidencia de
+/// `query`. Si la busqueda esta toda en minusculas no distingue mayusculas
+/// (smartcase, como en vim). Devuelve un vector vacio si no hay ninguna.
+pub fn matches(line: &str, query: &str) -> Vec<bool> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| match ignore_case {
+        true => c.to_lowercase().next().unwrap_or(c),
+        false => c,
+    };
+
+    let hay: Vec<char> = line.chars().map(fold).collect();
+    let needle: Vec<char> = query.chars().map(fold).collect();
+    if needle.is_empty() || needle.len() > hay.len() {
+        return Vec::new();
+    }
+
+    let mut marks = vec![false; hay.len()];
+    let mut found = false;
+    for start in 0..=hay.len() - needle.len() {
+        if hay[start..start + needle.len()] == needle[..] {
+            marks[start..start + needle.len()].fill(true);
+            found = true;
+        }
+    }
+
+    if found {
+        marks
+    } else {
+        Vec::new()
+    }
+}
+
+// Escribe `seg` agrupando los caracteres en tramos con y sin resaltado.
+// `offset` es la posicion del primer caracter de `seg` dentro de la linea.
+fn draw_highlighted(y: i32, x: i32, seg: &str, offset: usize, highlight: &[bool]) {
+    mv(y, x);
+    let mut run = String::new();
+    let mut run_on = false;
+
+    for (n, c) in seg.chars().enumerate() {
+        let on = highlight.get(offset + n).copied().unwrap_or(false);
+        if on != run_on && !run.is_empty() {
+            draw_run(&run, run_on);
+            run.clear();
+        }
+        run_on = on;
+        run.push(c);
+    }
+    draw_run(&run, run_on);
+}
+
+fn draw_run(s: &str, on: bool) {
+    if on {
+        attron(A_REVERSE());
+        addstr(s);
+        attroff(A_REVERSE());
+    } else {
+        addstr(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn index() {
+        let path = std::env::temp_dir().join(format!("mag-pager-{}", std::process::id()));
+        fs::write(&path, "uno\ndos\r\n\ntres").unwrap();
+
+        let mut index = LineIndex::open(&path).unwrap();
+        assert_eq!(index.line(1).as_deref(), Some("dos"));
+        assert!(!index.is_complete());
+
+        index.index_all();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.line(2).as_deref(), Some(""));
+        assert_eq!(index.line(3).as_deref(), Some("tres"));
+        assert_eq!(index.line(4), None);
+
+        // El fichero crece como un log
+        fs::write(&path, "uno\ndos\r\n\ntres y cuatro\ncinco\n").unwrap();
+        assert!(index.refresh());
+        index.index_all();
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.line(3).as_deref(), Some("tres y cuatro"));
+
+        // Y luego se trunca
+        fs::write(&path, "nuevo\n").unwrap();
+        assert!(index.refresh());
+        index.index_all();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.line(0).as_deref(), Some("nuevo"));
+
+        // Truncado y vuelto a crecer por encima de lo que media (copytruncate)
+        fs::write(&path, "otra cosa distinta\n").unwrap();
+        assert!(index.refresh());
+        assert_eq!(index.line(0).as_deref(), Some("otra cosa distinta"));
+
+        // Rotado: el nombre pasa a ser otro fichero
+        let old = path.with_extension("1");
+        fs::rename(&path, &old).unwrap();
+        fs::write(&path, "rotado\n").unwrap();
+        assert!(index.refresh());
+        assert_eq!(index.line(0).as_deref(), Some("rotado"));
+        fs::remove_file(&old).unwrap();
+
+        // Una linea enorme se corta sin partir las "ñ"
+        let long = "a".to_string() + &"ñ".repeat(MAX_LINE_BYTES as usize);
+        fs::write(&path, &long).unwrap();
+        let mut index = LineIndex::open(&path).unwrap();
+        let line = index.line(0).unwrap();
+        assert!(!line.contains('\u{fffd}'));
+        assert_eq!(line.len(), MAX_LINE_BYTES as usize - 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn smartcase() {
+        assert_eq!(
+            matches("Hola hola", "hola").iter().filter(|m| **m).count(),
+            8
+        );
+        assert_eq!(
+            matches("Hola hola", "Hola").iter().filter(|m| **m).count(),
+            4
+        );
+        assert!(matches("Hola", "adios").is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use ncurses::*;
+
+use crate::text;
+
+const ESC: i32 = 27;
+const ENTER: i32 = 10;
+const BACKSPACE: i32 = 127;
+// Ctrl-U borra toda la linea, como en la shell
+const KILL_LINE: i32 = 21;
+const TAB: i32 = 9;
+// Filas encima del prompt para la lista de opciones al completar
+const COMPLETION_ROWS: i32 = 5;
+
+/// Recibe lo escrito y devuelve las formas de completarlo.
+pub type Completer<'a> = &'a dyn Fn(&str) -> Vec<String>;
+
+/// Pide una linea de texto en la fila `y` de `win`. Devuelve `None` si el
+/// usuario cancela con Esc.
+///
+/// Funciona tanto si `win` esta en modo `nodelay` como si no: mientras no
+/// llega ninguna tecla simplemente se espera.
+pub fn read_line(win: WINDOW, y: i32, label: &str, initial: &str) -> Option<String> {
+    edit_line(win, y, label, initial, None)
+}
+
+/// Como `read_line`, pero con Tab se completa usando `complete`, que recibe
+/// lo escrito y devuelve las opciones completas. Si hay varias se añade lo
+/// que tienen en comun, se muestran encima del prompt y los siguientes Tab
+/// (o Shift-Tab) van pasando por ellas.
+///
+/// Las filas de encima quedan pintadas; quien llama tiene que repintar.
+pub fn read_line_completing(
+    win: WINDOW,
+    y: i32,
+    label: &str,
+    initial: &str,
+    complete: Completer,
+) -> Option<String> {
+    edit_line(win, y, label, initial, Some(complete))
+}
+
+fn edit_line(
+    win: WINDOW,
+    y: i32,
+    label: &str,
+    initial: &str,
+    complete: Option<Completer>,
+) -> Option<String> {
+    let mut input: Vec<char> = initial.chars().collect();
+    let width = getmaxx(win);
+    let mut candidates: Vec<String> = Vec::new();
+    let mut current: Option<usize> = None;
+
+    curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
+    let result = loop {
+        if !candidates.is_empty() {
+            show_candidates(win, y, &candidates, current);
+        }
+
+        let line: String = input.iter().collect();
+        let shown = format!("{}{}", label, text::sanitize(&line));
+        // Si no cabe mostramos el final, que es donde se esta escribiendo
+        let max = (width - 1).max(0) as usize;
+        let skip = text::width(&shown).saturating_sub(max);
+        let shown: String = shown.chars().skip(skip).collect();
+
+        wmove(win, y, 0);
+        wclrtoeol(win);
+        mvwaddstr(win, y, 0, &shown);
+        wrefresh(win);
+
+        let ch = wgetch(win);
+        if ch == ERR {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            continue;
+        }
+        if ch != TAB && ch != KEY_BTAB {
+            candidates.clear();
+            current = None;
+        }
+
+        match ch {
+            ESC => break None,
+            ENTER | KEY_ENTER => break Some(line),
+            TAB | KEY_BTAB if !candidates.is_empty() => {
+                let n = candidates.len();
+                let next = match (current, ch == TAB) {
+                    (None, true) => 0,
+                    (None, false) => n - 1,
+                    (Some(i), true) => (i + 1) % n,
+                    (Some(i), false) => (i + n - 1) % n,
+                };
+                current = Some(next);
+                input = candidates[next].chars().collect();
+            }
+            TAB => {
+                let Some(complete) = complete else {
+                    continue;
+                };
+                let found = complete(&line);
+                match found.len() {
+                    0 => {
+                        beep();
+                    }
+                    1 => input = found[0].chars().collect(),
+                    _ => {
+                        input = common_prefix(&found).chars().collect();
+                        candidates = found;
+                    }
+                }
+            }
+            BACKSPACE | KEY_BACKSPACE | 8 => {
+                input.pop();
+            }
+            KILL_LINE => input.clear(),
+            ch if ch >= 32 => {
+                if let Some(c) = read_char(win, ch) {
+                    input.push(c);
+                }
+            }
+            _ => {}
+        }
+    };
+    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+
+    wmove(win, y, 0);
+    wclrtoeol(win);
+    wrefresh(win);
+
+    result
+}
+
+fn common_prefix(strings: &[String]) -> String {
+    let mut prefix: Vec<char> = strings[0].chars().collect();
+    for s in &strings[1..] {
+        let same = prefix
+            .iter()
+            .zip(s.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(same);
+    }
+    prefix.into_iter().collect()
+}
+
+// Solo se muestra el ultimo componente de cada opcion, separadas por dos
+// espacios y en tantas filas como quepan
+fn show_candidates(win: WINDOW, y: i32, candidates: &[String], current: Option<usize>) {
+    let width = getmaxx(win).max(1) as usize;
+    let rows = COMPLETION_ROWS.min(y);
+    let first = y - rows;
+
+    for row in first..y {
+        wmove(win, row, 0);
+        wclrtoeol(win);
+    }
+
+    let (mut row, mut col) = (first, 0);
+    for (i, c) in candidates.iter().enumerate() {
+        let trimmed = c.trim_end_matches('/');
+        let start = trimmed.rfind('/').map_or(0, |p| p + 1);
+        let name = text::sanitize(&c[start..]);
+        let w = text::width(&name);
+
+        if col > 0 && col + w > width {
+            row += 1;
+            col = 0;
+        }
+        if row >= y {
+            break;
+        }
+
+        let attr = if Some(i) == current {
+            A_REVERSE()
+        } else {
+            A_NORMAL()
+        };
+        wattron(win, attr);
+        mvwaddstr(win, row, col as i32, &text::clip(&name, width));
+        wattroff(win, attr);
+        col += w + 2;
+    }
+}
+
+// `wgetch` devuelve los caracteres no ASCII byte a byte; juntamos los bytes
+// de continuacion para reconstruir el caracter UTF-8.
+fn read_char(win: WINDOW, first: i32) -> Option<char> {
+    if first > 0xff {
+        return None;
+    }
+    let first = first as u8;
+    let len = match first {
+        0x00..=0x7f => return Some(first as char),
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return None,
+    };
+
+    let mut bytes = vec![first];
+    while bytes.len() < len {
+        match wgetch(win) {
+            ERR => continue,
+            b @ 0x80..=0xbf => bytes.push(b as u8),
+            _ => return None,
+        }
+    }
+
+    std::str::from_utf8(&bytes).ok()?.chars().next()
+}
+
+/// Hace una pregunta de una sola tecla en la fila `y` de `win`. Devuelve la
+/// tecla pulsada si esta entre `choices`, o `None` si se cancela con Esc o
+/// cualquier otra tecla.
+pub fn ask(win: WINDOW, y: i32, question: &str, choices: &str) -> Option<char> {
+    wmove(win, y, 0);
+    wclrtoeol(win);
+    mvwaddstr(win, y, 0, &text::sanitize(question));
+    wrefresh(win);
+
+    let answer = loop {
+        match wgetch(win) {
+            ERR => std::thread::sleep(std::time::Duration::from_millis(10)),
+            ch => break read_char(win, ch).filter(|c| choices.contains(*c)),
+        }
+    };
+
+    wmove(win, y, 0);
+    wclrtoeol(win);
+    wrefresh(win);
+
+    answer
+}
+
+/// Como `ask`, pero acepta cualquier tecla imprimible; para pedir una letra
+/// de marca o similar.
+pub fn read_key(win: WINDOW, y: i32, question: &str) -> Option<char> {
+    wmove(win, y, 0);
+    wclrtoeol(win);
+    mvwaddstr(win, y, 0, &text::sanitize(question));
+    wrefresh(win);
+
+    let answer = loop {
+        match wgetch(win) {
+            ERR => std::thread::sleep(std::time::Duration::from_millis(10)),
+            ch => break read_char(win, ch).filter(|c| !c.is_control() && *c != ' '),
+        }
+    };
+
+    wmove(win, y, 0);
+    wclrtoeol(win);
+    wrefresh(win);
+
+    answer
+}
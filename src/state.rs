@@ -1,26 +1,73 @@
 use std::{
+    collections::BTreeSet,
     env,
     path::{Path, PathBuf},
-    sync::mpsc,
-    thread::{sleep, spawn},
+    thread::sleep,
     time::Duration,
 };
 
-use crate::{filesys::MagFolder, pos::Pos, ui::MagWindow};
+use crate::{
+    archive,
+    bookmarks::Bookmarks,
+    breadcrumb::{self, Segment},
+    cache, complete, config,
+    filesys::{self, Sort},
+    frecency::Frecency,
+    history::History,
+    jobs::Jobs,
+    layout::{self, Columns, Layout, Miller, Pane, MAX_PARENTS},
+    menu,
+    mouse::{self, Action, WHEEL_STEP},
+    ops::{self, Conflict},
+    pager::Pager,
+    pos::Pos,
+    prompt,
+    tab::{Tab, View},
+    text,
+    ui::{MagWindow, INFO_Y},
+    vfs,
+    watcher::Watcher,
+};
 use ncurses::*;
 
-const W_RIGHT: f32 = 0.2;
-const W_MIDDLE: f32 = 0.4;
-const W_LEFT: f32 = 0.4;
-const START_TOP: i32 = 1;
+// Por debajo de esto no caben las columnas y se muestra un aviso
+const MIN_WIDTH: i32 = 30;
+const MIN_HEIGHT: i32 = 5;
 
 #[derive(Debug)]
 pub struct State {
     pub child_win: MagWindow,
     pub mid_win: MagWindow,
     pub parent_win: MagWindow,
+    /// El segundo panel en la disposicion de dos paneles.
+    pub other_win: MagWindow,
+    /// Con dos paneles, si el activo (`mid_win`) es el de la derecha.
+    pub active_right: bool,
+    pub layout: &'static dyn Layout,
+    pub columns: Columns,
+    /// Columnas de padres a partir del segundo nivel (el primero es
+    /// `parent_win`), de mas cerca a mas lejos.
+    pub ancestors: Vec<MagWindow>,
     pub path: PathBuf,
+    /// Tamaño de la pantalla con el que se colocaron las ventanas.
     pub dim: Pos<i32>,
+    pub dir_details: bool,
+    pub jobs: Jobs,
+    pub message: Option<String>,
+    /// `None` si inotify no esta disponible; entonces no hay refresco
+    /// automatico.
+    pub watcher: Option<Watcher>,
+    pub history: History,
+    pub bookmarks: Bookmarks,
+    pub frecency: Frecency,
+    /// Orden y filtro de la pestaña activa.
+    pub view: View,
+    /// Todas las pestañas en orden; la activa es `None` porque sus ventanas
+    /// son las de arriba.
+    pub tabs: Vec<Option<Tab>>,
+    pub tab: usize,
+    /// Lo copiado con `y`, para pegarlo con `p` en cualquier pestaña.
+    pub yanked: Vec<PathBuf>,
 }
 
 impl State {
@@ -31,11 +78,14 @@ impl State {
             false => env::current_dir()?,
         };
 
+        setlocale(LcCategory::all, "");
         initscr();
         noecho();
         curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
         raw();
         cbreak();
+        keypad(stdscr(), true);
+        mouse::init();
         refresh();
         start_color();
         init_pair(1, COLOR_WHITE, COLOR_BLACK);
@@ -44,179 +94,1152 @@ impl State {
         init_pair(4, COLOR_BLUE, COLOR_BLACK); // folder normal
         init_pair(5, COLOR_BLACK, COLOR_BLUE); // folder selected
 
-        let w = getmaxx(stdscr());
-        let h = getmaxy(stdscr()) - 3;
-
-        let w_left = (w as f32 * W_RIGHT) as i32;
-        let w_middle = (w as f32 * W_MIDDLE) as i32;
-        let w_right = (w as f32 * W_LEFT) as i32;
-
-        let dim = Pos::new(h, w);
-
-        let parent_win = MagWindow::new(
-            &path.parent().unwrap(),
-            Pos::new(1, START_TOP),
-            Pos::new(w_right, h - START_TOP),
-        )
-        .fetch_return();
-
-        let mid_win = MagWindow::new(
-            &path,
-            Pos::new(1, START_TOP),
-            Pos::new(w_middle, h - START_TOP),
-        )
-        .fetch_return();
-
-        let child_win = if mid_win.dir.get_folder().unwrap().items[0].is_folder() {
-            MagWindow::new(
-                &mid_win.dir.get_folder_path(0).unwrap(),
-                Pos::new(1, START_TOP),
-                Pos::new(w_left, h - START_TOP),
-            )
-            .fetch_return()
-        } else {
-            MagWindow::new_file(
-                &mid_win.dir.get_folder_path(0).unwrap(),
-                Pos::new(1, START_TOP),
-                Pos::new(w_left, h - START_TOP),
-            )
-            .fetch_return()
-        };
+        let tab = Tab::open(&path);
+
+        let mut history = History::load();
+        history.visit(&path);
+        let mut frecency = Frecency::load();
+        frecency.visit(&path);
 
-        Ok(Self {
-            parent_win,
-            child_win,
-            mid_win,
+        let mut state = Self {
+            parent_win: tab.parent_win,
+            child_win: tab.child_win,
+            mid_win: tab.mid_win,
+            other_win: tab.other_win,
+            active_right: tab.active_right,
+            layout: layout::by_name(&config::get().layout).unwrap_or(&Miller),
+            columns: Columns::from_config(),
+            ancestors: Vec::new(),
             path,
-            dim,
-        })
+            dim: Pos::new(0, 0),
+            dir_details: config::get().dir_details,
+            jobs: Jobs::default(),
+            message: None,
+            watcher: Watcher::new().ok(),
+            history,
+            bookmarks: Bookmarks::load(),
+            frecency,
+            view: tab.view,
+            tabs: vec![None],
+            tab: 0,
+            yanked: Vec::new(),
+        };
+        state.layout();
+        Ok(state)
     }
 
     pub fn update(&mut self) -> std::io::Result<&mut Self> {
-        if self.child_win.dir.is_folder() {
-            let (tx, rx) = mpsc::channel();
-            let mut thx_dir = MagFolder::new(&self.child_win.path);
-            let mut size = self.child_win.dir.get_folder().unwrap().items.len();
-            thx_dir.get_entries();
-
-            spawn(move || loop {
-                sleep(Duration::from_secs(1));
-                thx_dir.get_entries();
-                if thx_dir.items.len() != size {
-                    tx.send(21312).unwrap();
-                    size = thx_dir.items.len();
+        nodelay(stdscr(), true);
+
+        let mut ch = getch();
+        self.display();
+        loop {
+            if ch == 113 && self.confirm_quit() {
+                break;
+            }
+
+            if self.too_small() {
+                // Hasta que vuelva a crecer solo se puede salir
+                if ch == KEY_RESIZE {
+                    self.handle_resize();
                 }
+            } else {
+                // Si no hay teclas presionadas, `getch()` devolverá ERR (-1)
+                self.poll_loads();
+                self.poll_watcher();
+                self.poll_jobs();
+                self.history.autosave();
+                self.frecency.autosave();
+
+                self.handle_key(ch)?;
+            }
+
+            ch = getch();
+            sleep(Duration::from_millis(10));
+        }
+
+        Ok(self)
+    }
+
+    fn confirm_quit(&mut self) -> bool {
+        if self.jobs.is_empty() {
+            return true;
+        }
+
+        let answer = prompt::ask(
+            stdscr(),
+            LINES() - 1,
+            "jobs still running, quit anyway? [y/n]",
+            "yn",
+        );
+        self.display_status();
+        answer == Some('y')
+    }
+
+    // Vigila las tres columnas; tras navegar cambian los directorios, por
+    // eso se vuelve a apuntar en cada vuelta (no hace nada si son los mismos)
+    fn poll_watcher(&mut self) {
+        let mut dirs = vec![self.parent_win.path.clone(), self.mid_win.path.clone()];
+        if self.child_win.dir.is_folder() {
+            dirs.push(self.child_win.path.clone());
+        }
+        if self.shows(Pane::Other) {
+            dirs.push(self.other_win.path.clone());
+        }
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        let dirs: Vec<&Path> = dirs.iter().map(PathBuf::as_path).collect();
+        watcher.watch(&dirs);
+
+        let changed = watcher.poll();
+        if !changed.is_empty() {
+            self.apply_changes(&changed);
+        }
+    }
+
+    // Los directorios se recargan en segundo plano; `poll_loads` los pinta
+    // cuando terminan
+    fn apply_changes(&mut self, changed: &BTreeSet<PathBuf>) {
+        for path in changed {
+            cache::invalidate(path);
+        }
+        for win in [&mut self.parent_win, &mut self.mid_win, &mut self.other_win] {
+            if changed.contains(&win.path) {
+                win.reload();
+            }
+        }
+        if changed.contains(&self.child_win.path) {
+            self.child_win.reload();
+            if self.child_win.dir.is_file() {
+                werase(self.child_win.win);
+                self.display_child();
+            }
+        }
+    }
+
+    // Recoge las entradas que han leido los hilos de fondo
+    fn poll_loads(&mut self) {
+        if self.parent_win.poll_load() && self.shows(Pane::Parent) {
+            werase(self.parent_win.win);
+            self.parent_win.display();
+        }
+
+        if self.other_win.poll_load() && self.shows(Pane::Other) {
+            werase(self.other_win.win);
+            self.other_win.display();
+        }
+
+        if self.mid_win.poll_load() {
+            // Si la entrada que se previsualizaba ha desaparecido el cursor
+            // esta ahora en otra
+            let selected = self.mid_win.selected().map(|e| e.get_path());
+            if selected != Some(&self.child_win.path) {
+                self.update_child();
+            } else {
+                werase(self.mid_win.win);
+                self.display_mid();
+            }
+        }
+
+        let shows_parents = self.shows(Pane::Parent);
+        for win in &mut self.ancestors {
+            if win.poll_load() && shows_parents {
+                werase(win.win);
+                win.display();
+            }
+        }
+
+        if self.child_win.poll_load() {
+            werase(self.child_win.win);
+            self.display_child();
+        }
+    }
+
+    fn poll_jobs(&mut self) {
+        if self.jobs.is_empty() {
+            return;
+        }
+
+        let finished = self.jobs.take_finished();
+        if finished.is_empty() {
+            self.display_status();
+            return;
+        }
+
+        for job in finished {
+            self.message = Some(match job.result {
+                Some(Ok(summary)) => format!("{}: {}", job.name, summary),
+                Some(Err(e)) => format!("{} failed: {}", job.name, e),
+                None => job.name,
             });
-            nodelay(stdscr(), true);
+        }
+        self.refresh_listings();
+    }
 
-            let mut ch = getch();
-            self.display();
-            while ch != 113 {
-                // Si no hay teclas presionadas, `getch()` devolverá ERR (-1)
-                if let Ok(value) = rx.try_recv() {
-                    if value > 100 {
-                        self.child_win.fetch();
-                        self.display();
-                    }
+    // Vuelve a leer la columna del medio despues de que algo la haya
+    // cambiado; el cursor se mantiene al llegar las entradas
+    fn refresh_listings(&mut self) {
+        self.mid_win.reload();
+        self.child_win.reload();
+        self.redraw();
+    }
+
+    fn handle_key(&mut self, ch: i32) -> std::io::Result<()> {
+        let layout = self.layout;
+        if layout.handle_key(self, ch) {
+            return Ok(());
+        }
+
+        match ch {
+            KEY_RESIZE => self.handle_resize(),
+            KEY_MOUSE => self.handle_mouse()?,
+            //VIM movment keys
+            //h
+            104 => self.handle_movment_left()?,
+            //j
+            106 => self.handle_movment_down()?,
+            //k
+            107 => self.handle_movment_up()?,
+            //l
+            108 => self.handle_movment_right()?,
+            // Scroll de la preview
+            //J
+            74 => self.handle_preview_scroll(|w| w.scroll_preview(1)),
+            //K
+            75 => self.handle_preview_scroll(|w| w.scroll_preview(-1)),
+            //Ctrl-F
+            6 | KEY_NPAGE => self.handle_preview_scroll(|w| w.scroll_preview_page(1)),
+            //Ctrl-B
+            2 | KEY_PPAGE => self.handle_preview_scroll(|w| w.scroll_preview_page(-1)),
+            //>
+            62 => self.handle_preview_scroll(MagWindow::scroll_preview_end),
+            //<
+            60 => self.handle_preview_scroll(MagWindow::scroll_preview_start),
+            //Enter
+            10 | KEY_ENTER => self.handle_open_pager(),
+            //space
+            32 => {
+                self.mid_win.toggle_mark();
+                self.display_mid();
+                self.handle_movment_down()?;
+            }
+            //x
+            120 => self.handle_extract(false),
+            //X
+            88 => self.handle_extract(true),
+            //c
+            99 => self.handle_compress(),
+            //H
+            72 => {
+                if let Some(dir) = self.history.back() {
+                    self.show_dir(&dir);
                 }
+            }
+            //L
+            76 => {
+                if let Some(dir) = self.history.forward() {
+                    self.show_dir(&dir);
+                }
+            }
+            //Ctrl-O
+            15 => self.handle_history_list(),
+            //m
+            109 => self.handle_set_mark(),
+            //'
+            39 => self.handle_jump_mark(),
+            //M
+            77 => self.handle_bookmark_list(),
+            //:
+            58 => self.handle_goto_path(),
+            //b
+            98 => self.handle_breadcrumb(),
+            //z
+            122 if self.view.tree => self.handle_fold(),
+            122 => self.handle_jump_frecent(false),
+            //.
+            46 => {
+                self.view.hidden = !self.view.hidden;
+                self.apply_view();
+                let state = if self.view.hidden { "shown" } else { "hidden" };
+                self.set_message(format!("dotfiles {}", state));
+            }
+            //F
+            70 => {
+                self.view.flatten = match self.view.flatten {
+                    Some(_) => None,
+                    None => Some(config::get().flatten_depth),
+                };
+                self.apply_view();
+            }
+            //+
+            43 => self.handle_flatten_depth(1),
+            //-
+            45 => self.handle_flatten_depth(-1),
+            //T
+            84 => {
+                self.view.tree = !self.view.tree;
+                self.apply_view();
+                let state = if self.view.tree { "on" } else { "off" };
+                self.set_message(format!("tree view {}", state));
+            }
+            //Z
+            90 => self.handle_jump_frecent(true),
+            //t
+            116 => self.handle_new_tab(),
+            //Ctrl-W
+            23 => self.handle_close_tab(),
+            //Tab
+            9 => self.switch_tab((self.tab + 1) % self.tabs.len()),
+            KEY_BTAB => self.switch_tab((self.tab + self.tabs.len() - 1) % self.tabs.len()),
+            //1-9
+            49..=57 => self.switch_tab((ch - 49) as usize),
+            //y
+            121 => self.handle_yank(),
+            //p
+            112 => self.handle_paste(None),
+            //P
+            80 => {
+                let tab = prompt::read_key(stdscr(), LINES() - 1, "paste into tab: ");
+                match tab.and_then(|c| c.to_digit(10)) {
+                    Some(n @ 1..=9) => self.handle_paste(Some(n as usize - 1)),
+                    _ => self.display_status(),
+                }
+            }
+            //F5
+            ch if ch == KEY_F(5) => self.handle_copy(false),
+            //F6
+            ch if ch == KEY_F(6) => self.handle_copy(true),
+            //v
+            118 => self.handle_switch_layout(),
+            //[ ] ( ) _ = { } w W
+            91 | 93 | 40 | 41 | 95 | 61 | 123 | 125 | 119 | 87 => self.handle_columns(ch),
+            //f
+            102 => self.handle_filter(),
+            //s
+            115 => self.handle_sort(),
+            //i
+            105 => {
+                self.dir_details = !self.dir_details;
+                werase(self.child_win.win);
+                self.display_child();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_new_tab(&mut self) {
+        let tab = Tab::open(&self.mid_win.path);
+        self.tabs.insert(self.tab + 1, Some(tab));
+        self.switch_tab(self.tab + 1);
+    }
 
-                match ch {
-                    104 => self.handle_movment_left()?,
-                    //j
-                    106 => self.handle_movment_down()?,
-                    //k
-                    107 => self.handle_movment_up()?,
-                    //l
-                    108 => self.handle_movment_right()?,
-                    _ => {}
+    fn handle_close_tab(&mut self) {
+        if self.tabs.len() == 1 {
+            self.set_message("can't close the last tab");
+            return;
+        }
+
+        let next = match self.tab + 1 < self.tabs.len() {
+            true => self.tab + 1,
+            false => self.tab - 1,
+        };
+        let mut tab = self.tabs[next].take().unwrap();
+        self.swap_tab(&mut tab);
+        tab.close();
+        self.tabs.remove(self.tab);
+        self.tab = next.min(self.tab);
+        self.open_tab();
+    }
+
+    fn switch_tab(&mut self, i: usize) {
+        if i == self.tab || i >= self.tabs.len() {
+            return;
+        }
+
+        let mut tab = self.tabs[i].take().unwrap();
+        self.swap_tab(&mut tab);
+        self.tabs[self.tab] = Some(tab);
+        self.tab = i;
+        self.open_tab();
+    }
+
+    fn swap_tab(&mut self, tab: &mut Tab) {
+        std::mem::swap(&mut self.parent_win, &mut tab.parent_win);
+        std::mem::swap(&mut self.mid_win, &mut tab.mid_win);
+        std::mem::swap(&mut self.child_win, &mut tab.child_win);
+        std::mem::swap(&mut self.other_win, &mut tab.other_win);
+        std::mem::swap(&mut self.active_right, &mut tab.active_right);
+        std::mem::swap(&mut self.view, &mut tab.view);
+    }
+
+    /// Con dos paneles, pasa a ser activo el otro.
+    pub fn swap_panes(&mut self) {
+        std::mem::swap(&mut self.mid_win, &mut self.other_win);
+        self.active_right = !self.active_right;
+        let path = self.mid_win.path.clone();
+        self.parent_win
+            .change_dir(path.parent().unwrap_or(&path), true);
+        self.parent_win.select(&path);
+        self.set_views();
+        self.update_child();
+        self.redraw();
+    }
+
+    fn handle_switch_layout(&mut self) {
+        let all = &layout::LAYOUTS;
+        let i = all
+            .iter()
+            .position(|l| l.name() == self.layout.name())
+            .unwrap_or(0);
+        self.layout = all[(i + 1) % all.len()];
+        self.layout();
+        self.update_child();
+        self.redraw();
+        self.set_message(format!("layout: {}", self.layout.name()));
+    }
+
+    // Solo se vigilan los directorios de la pestaña activa, asi que al
+    // volver a una se releen por si han cambiado mientras tanto
+    fn open_tab(&mut self) {
+        self.layout();
+        self.parent_win.reload();
+        self.mid_win.reload();
+        self.update_child();
+        self.redraw();
+    }
+
+    fn handle_yank(&mut self) {
+        self.yanked = self.mid_win.selection();
+        self.mid_win.marked.clear();
+        self.display_mid();
+        self.set_message(format!("{} yanked", self.yanked.len()));
+    }
+
+    // Pega lo copiado en el directorio de la pestaña `tab`, o en el actual
+    fn handle_paste(&mut self, tab: Option<usize>) {
+        if self.yanked.is_empty() {
+            self.set_message("nothing yanked");
+            return;
+        }
+        let dest = match tab {
+            None => self.mid_win.path.clone(),
+            Some(i) if i == self.tab => self.mid_win.path.clone(),
+            Some(i) => match self.tabs.get(i) {
+                Some(Some(t)) => t.mid_win.path.clone(),
+                _ => {
+                    self.set_message(format!("no tab {}", i + 1));
+                    return;
                 }
+            },
+        };
+        if vfs::is_virtual(&dest) {
+            self.set_message("read-only: inside an archive");
+            return;
+        }
+
+        let sources = self.yanked.clone();
+        self.spawn_copy(sources, dest, false);
+    }
 
-                ch = getch();
-                sleep(Duration::from_millis(10));
+    // Copia o mueve la seleccion; propone el directorio que diga la
+    // disposicion (con dos paneles, el del otro)
+    fn handle_copy(&mut self, move_: bool) {
+        let sources = self.mid_win.selection();
+        if sources.is_empty() {
+            return;
+        }
+        if move_ && vfs::is_virtual(&self.mid_win.path) {
+            self.set_message("read-only: inside an archive");
+            return;
+        }
+
+        // El texto del prompt es solo para mostrar: si no se cambia se usa la
+        // ruta tal cual, aunque no sea UTF-8
+        let base = self.mid_win.path.clone();
+        let target = self.layout.target_dir(self);
+        let mut initial = target.to_string_lossy().to_string();
+        if !initial.ends_with('/') {
+            initial.push('/');
+        }
+        let label = if move_ { "move to: " } else { "copy to: " };
+        let complete = |input: &str| complete::complete_path(input, &base);
+        let input = prompt::read_line_completing(stdscr(), LINES() - 1, label, &initial, &complete);
+        self.redraw();
+        let Some(input) = input.filter(|i| !i.trim().is_empty()) else {
+            return;
+        };
+
+        let dest = match input == initial {
+            true => target,
+            false => complete::expand(input.trim(), &base),
+        };
+        if !dest.is_dir() {
+            self.set_message(format!(
+                "{}: not a directory",
+                text::sanitize_os_str(dest.as_os_str())
+            ));
+            return;
+        }
+        self.mid_win.marked.clear();
+        self.display_mid();
+        self.spawn_copy(sources, dest, move_);
+    }
+
+    fn spawn_copy(&mut self, sources: Vec<PathBuf>, dest: PathBuf, move_: bool) {
+        if vfs::is_virtual(&dest) {
+            self.set_message("read-only: inside an archive");
+            return;
+        }
+
+        let existing = sources
+            .iter()
+            .filter(|s| dest.join(s.file_name().unwrap_or_default()).exists())
+            .count();
+        let mut conflict = Conflict::Skip;
+        if existing > 0 {
+            let question = format!(
+                "{} entries already exist: [o]verwrite, [s]kip, [r]ename",
+                existing
+            );
+            match self.ask_conflict(&question) {
+                Some(c) => conflict = c,
+                None => return,
+            }
+        }
+
+        let name = format!(
+            "{} to {}",
+            if move_ { "move" } else { "copy" },
+            text::sanitize_os_str(dest.file_name().unwrap_or(dest.as_os_str()))
+        );
+        self.jobs.spawn(name, move |p| {
+            let report = match move_ {
+                true => ops::move_to(&sources, &dest, conflict, p),
+                false => ops::copy(&sources, &dest, conflict, p),
+            };
+            report.map(|r| r.summary())
+        });
+        self.display_status();
+    }
+
+    // En el arbol `z` pliega y despliega como en vim: `zo`, `zc`, `za` y `zM`
+    // para plegarlo todo. Con cualquier otra tecla es el salto de `z`
+    fn handle_fold(&mut self) {
+        match prompt::read_key(stdscr(), LINES() - 1, "z") {
+            Some('o') => {
+                self.mid_win.expand();
             }
+            Some('c') => self.mid_win.collapse(),
+            Some('a') => match self.mid_win.is_expanded() {
+                true => self.mid_win.collapse(),
+                false => {
+                    self.mid_win.expand();
+                }
+            },
+            Some('M') => self.mid_win.collapse_all(),
+            _ => return self.handle_jump_frecent(false),
+        }
+        werase(self.mid_win.win);
+        self.update_child();
+        self.display_status();
+    }
+
+    fn handle_flatten_depth(&mut self, delta: isize) {
+        let Some(depth) = self.view.flatten else {
+            return;
+        };
+        let depth = depth.saturating_add_signed(delta).max(1);
+        self.view.flatten = Some(depth);
+        self.apply_view();
+        self.set_message(format!("flatten depth {}", depth));
+    }
+
+    fn handle_filter(&mut self) {
+        let current = self.view.filter.clone().unwrap_or_default();
+        let Some(input) = prompt::read_line(stdscr(), LINES() - 1, "filter: ", &current) else {
+            self.display_status();
+            return;
+        };
+        self.view.filter = Some(input.trim().to_string()).filter(|f| !f.is_empty());
+        self.apply_view();
+    }
+
+    fn handle_sort(&mut self) {
+        let question = "sort by: [n]ame [s]ize [m]odified";
+        let answer = prompt::ask(stdscr(), LINES() - 1, question, "nsm");
+        if let Some(sort) = answer.and_then(Sort::from_key) {
+            self.view.sort = sort;
+            self.apply_view();
         } else {
-            self.display();
-            let mut ch = getch();
-            while ch != 113 {
-                match ch {
-                    //VIM movment keys
-                    //h
-                    104 => self.handle_movment_left()?,
-                    //j
-                    106 => self.handle_movment_down()?,
-                    //k
-                    107 => self.handle_movment_up()?,
-                    //l
-                    108 => self.handle_movment_right()?,
-                    _ => {}
+            self.display_status();
+        }
+    }
+
+    fn apply_view(&mut self) {
+        self.set_views();
+        self.update_child();
+        self.redraw();
+    }
+
+    fn handle_history_list(&mut self) {
+        let entries = self.history.entries();
+        let current = self.history.pos();
+        // La mas reciente arriba
+        let items: Vec<String> = entries
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, p)| {
+                let mark = if i == current { ">" } else { " " };
+                format!("{} {}", mark, text::sanitize_os_str(p.as_os_str()))
+            })
+            .collect();
+        let selected = entries.len().saturating_sub(current + 1);
+
+        let choice = menu::pick("history (enter: go, q: close)", &items, selected, "");
+        match choice {
+            Some((i, _)) => {
+                let dir = entries[entries.len() - 1 - i].clone();
+                self.goto_dir(dir);
+            }
+            None => self.redraw(),
+        }
+    }
+
+    fn handle_set_mark(&mut self) {
+        let Some(key) = prompt::read_key(stdscr(), LINES() - 1, "mark: ") else {
+            self.display_status();
+            return;
+        };
+        let path = self.mid_win.path.clone();
+        self.bookmarks.set(key, &path);
+        self.set_message(format!(
+            "mark {}: {}",
+            key,
+            text::sanitize_os_str(path.as_os_str())
+        ));
+    }
+
+    fn handle_jump_mark(&mut self) {
+        let Some(key) = prompt::read_key(stdscr(), LINES() - 1, "jump to mark: ") else {
+            self.display_status();
+            return;
+        };
+        match self.bookmarks.get(key).cloned() {
+            Some(path) if vfs::is_browsable(&path) => self.goto_dir(path),
+            Some(path) => self.set_message(format!(
+                "mark {}: {} not found",
+                key,
+                text::sanitize_os_str(path.as_os_str())
+            )),
+            None => self.set_message(format!("mark {} not set", key)),
+        }
+    }
+
+    fn handle_bookmark_list(&mut self) {
+        let mut idx = 0;
+        loop {
+            let marks = self.bookmarks.list();
+            let items: Vec<String> = marks
+                .iter()
+                .map(|m| {
+                    let shared = if m.shared { "  (config)" } else { "" };
+                    let path = text::sanitize_os_str(m.path.as_os_str());
+                    format!("{}  {}{}", m.key, path, shared)
+                })
+                .collect();
+
+            let title = "bookmarks (enter: go, e: edit, d: delete, q: close)";
+            let Some((i, action)) = menu::pick(title, &items, idx, "ed") else {
+                self.redraw();
+                return;
+            };
+            idx = i;
+            let mark = &marks[i];
+
+            match action {
+                'd' => {
+                    if !self.bookmarks.remove(mark.key) {
+                        self.message = Some("marks from the config file can't be deleted".into());
+                    }
+                }
+                'e' => {
+                    // Una ruta que no es UTF-8 solo se puede mostrar con
+                    // perdidas; si no se toca se guarda la original
+                    let initial = mark.path.to_string_lossy();
+                    let label = format!("mark {}: ", mark.key);
+                    let base = self.mid_win.path.clone();
+                    let complete = |input: &str| complete::complete_path(input, &base);
+                    let input = prompt::read_line_completing(
+                        stdscr(),
+                        LINES() - 1,
+                        &label,
+                        &initial,
+                        &complete,
+                    );
+                    let Some(input) = input.filter(|i| !i.trim().is_empty()) else {
+                        continue;
+                    };
+                    if input != initial {
+                        let path = complete::expand(input.trim(), &base);
+                        self.bookmarks.set(mark.key, path);
+                    }
                 }
+                _ => {
+                    let path = mark.path.clone();
+                    self.redraw();
+                    if vfs::is_browsable(&path) {
+                        self.goto_dir(path);
+                    } else {
+                        self.set_message(format!(
+                            "{} not found",
+                            text::sanitize_os_str(path.as_os_str())
+                        ));
+                    }
+                    return;
+                }
+            }
+        }
+    }
 
-                ch = getch();
+    fn handle_goto_path(&mut self) {
+        let base = self.mid_win.path.clone();
+        let complete = |input: &str| complete::complete_path(input, &base);
+        let input = prompt::read_line_completing(stdscr(), LINES() - 1, "cd: ", "", &complete);
+        self.redraw();
+        let Some(input) = input.filter(|i| !i.trim().is_empty()) else {
+            return;
+        };
+
+        let path = complete::expand(input.trim(), &base);
+        match vfs::stat(&path) {
+            Ok(_) if vfs::is_browsable(&path) => self.goto_dir(path),
+            // Un fichero: se abre su directorio con el seleccionado
+            Ok(_) => {
+                self.goto_dir(path.parent().unwrap_or(&path));
+                self.mid_win.select(&path);
+                self.update_child();
             }
+            Err(e) => self.set_message(format!(
+                "{}: {}",
+                text::sanitize_os_str(path.as_os_str()),
+                filesys::io_error_message(&e)
+            )),
         }
+    }
 
-        Ok(self)
+    // Salta al directorio mas frecuente que encaje con lo escrito; con
+    // `choose` se muestran todos los que encajan para elegir
+    fn handle_jump_frecent(&mut self, choose: bool) {
+        let label = if choose { "zi: " } else { "z: " };
+        let Some(input) = prompt::read_line(stdscr(), LINES() - 1, label, "") else {
+            self.display_status();
+            return;
+        };
+        let keywords: Vec<&str> = input.split_whitespace().collect();
+
+        let mut found = Vec::new();
+        for dir in self.frecency.query(&keywords) {
+            if vfs::is_browsable(&dir) {
+                found.push(dir);
+            } else {
+                // Ya no existe
+                self.frecency.remove(&dir);
+            }
+        }
+        found.retain(|d| *d != self.mid_win.path);
+
+        if found.is_empty() {
+            self.set_message(format!("z: no match for {}", text::sanitize(&input)));
+            return;
+        }
+        if !choose {
+            self.goto_dir(&found[0]);
+            return;
+        }
+
+        let items: Vec<String> = found
+            .iter()
+            .map(|d| text::sanitize_os_str(d.as_os_str()))
+            .collect();
+        match menu::pick("frecent directories (enter: go, q: close)", &items, 0, "") {
+            Some((i, _)) => self.goto_dir(&found[i]),
+            None => self.redraw(),
+        }
     }
 
-    fn handle_movment_down(&mut self) -> std::io::Result<()> {
-        let len = self.mid_win.dir.get_folder().unwrap().items.len();
-        if self.mid_win.idx < len - 1 && len > 1 {
-            self.mid_win.idx += 1;
-            if self.mid_win.dir.get_folder().unwrap().items[self.mid_win.idx].is_folder() {
-                self.child_win.change_dir(
-                    self.mid_win.dir.get_folder().unwrap().items[self.mid_win.idx].get_path(),
-                    true,
-                );
+    /// Abre `path` en la columna del medio y lo apunta en el historial.
+    pub fn goto_dir<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        self.show_dir(path);
+        self.history.visit(path);
+    }
+
+    // Como `goto_dir` pero sin tocar el historial, para moverse por el
+    fn show_dir(&mut self, path: &Path) {
+        self.track(path);
+        self.mid_win.change_dir(path, true);
+        self.parent_win
+            .change_dir(path.parent().unwrap_or(path), true);
+        self.parent_win.select(path);
+        self.update_child();
+        self.redraw();
+    }
+
+    // Cuenta la visita para `z`. Lo de dentro de los archivos comprimidos no
+    fn track(&mut self, dir: &Path) {
+        if !vfs::is_virtual(dir) {
+            self.frecency.visit(dir);
+        }
+    }
+
+    fn handle_mouse(&mut self) -> std::io::Result<()> {
+        let Some(event) = mouse::read() else {
+            return Ok(());
+        };
+        let (y, x) = (event.y, event.x);
+        let delta = match event.action {
+            Action::WheelUp => -WHEEL_STEP,
+            Action::WheelDown => WHEEL_STEP,
+            Action::Click | Action::DoubleClick => {
+                return self.handle_click(y, x, event.action == Action::DoubleClick)
+            }
+        };
+
+        if self.shows(Pane::Mid) && self.mid_win.encloses(y, x) {
+            self.mid_win.move_cursor(delta);
+            self.update_child();
+        } else if self.shows(Pane::Child) && self.child_win.encloses(y, x) {
+            if self.child_win.dir.is_file() {
+                self.handle_preview_scroll(|w| w.scroll_preview(delta));
             } else {
-                self.child_win.change_dir(
-                    self.mid_win.dir.get_folder().expect("ESTOY AQUI").items[self.mid_win.idx]
-                        .get_path(),
-                    false,
-                );
+                self.child_win.move_cursor(delta);
+                werase(self.child_win.win);
+                self.display_child();
             }
+        } else if let Some(win) = self.window_at(y, x) {
+            win.move_cursor(delta);
+            werase(win.win);
+            win.display();
+        }
+        Ok(())
+    }
 
-            wclear(self.child_win.win);
-            self.child_win.display();
-            self.mid_win.display();
+    fn handle_click(&mut self, y: i32, x: i32, double: bool) -> std::io::Result<()> {
+        if y == INFO_Y && self.shows(Pane::Mid) {
+            if let Some(segment) = self.mid_win.breadcrumb_at(x) {
+                self.jump_to_segment(segment);
+            }
+            return Ok(());
         }
 
+        // Con dos paneles, pinchar en el otro lo activa
+        if self.shows(Pane::Other) && self.other_win.encloses(y, x) {
+            self.swap_panes();
+        }
+
+        if self.shows(Pane::Mid) && self.mid_win.encloses(y, x) {
+            if let Some(idx) = self.mid_win.entry_at(y, false) {
+                self.mid_win.idx = idx;
+                self.update_child();
+                if double {
+                    self.open_child()?;
+                }
+            }
+        } else if self.shows(Pane::Child) && self.child_win.encloses(y, x) {
+            if let Some(idx) = self.child_win.entry_at(y, self.child_details()) {
+                self.child_win.idx = idx;
+                self.handle_movment_right()?;
+            }
+            if double {
+                self.open_child()?;
+            }
+        } else if let Some(win) = self.window_at(y, x) {
+            // Una columna de padres: se va a ese directorio con la entrada
+            // seleccionada
+            let Some(idx) = win.entry_at(y, false) else {
+                return Ok(());
+            };
+            win.idx = idx;
+            let (dir, path) = match win.selected() {
+                Some(e) => (win.path.clone(), e.get_path().clone()),
+                None => return Ok(()),
+            };
+            self.goto_dir(dir);
+            self.mid_win.select(&path);
+            self.update_child();
+        }
         Ok(())
     }
 
-    fn handle_movment_up(&mut self) -> std::io::Result<()> {
-        //let len = self.mid_win.dir.get_folder().unwrap().items.len();
-        if self.mid_win.idx >= 1 {
-            self.mid_win.idx -= 1;
-            if self.mid_win.dir.get_folder().unwrap().items[self.mid_win.idx].is_folder() {
-                self.child_win.change_dir(
-                    self.mid_win.dir.get_folder().unwrap().items[self.mid_win.idx].get_path(),
-                    true,
+    // Salta con el teclado a un directorio de la ruta de la cabecera
+    fn handle_breadcrumb(&mut self) {
+        if !self.shows(Pane::Mid) {
+            return;
+        }
+        self.mid_win.display_hints(stdscr());
+        let key = prompt::read_key(stdscr(), LINES() - 1, "jump to: ");
+        self.mid_win.display_info(stdscr());
+
+        let segments = self.mid_win.breadcrumb();
+        let segment = key
+            .and_then(|k| breadcrumb::HINTS.find(k))
+            .and_then(|i| segments.get(i).cloned());
+        match segment {
+            Some(segment) => self.jump_to_segment(segment),
+            None => self.display_status(),
+        }
+    }
+
+    // El `…` deja elegir entre los directorios que oculta
+    fn jump_to_segment(&mut self, segment: Segment) {
+        let dir = match segment.dirs.as_slice() {
+            [dir] => dir.clone(),
+            dirs => {
+                let items: Vec<String> = dirs
+                    .iter()
+                    .map(|d| text::sanitize_os_str(d.as_os_str()))
+                    .collect();
+                let last = items.len().saturating_sub(1);
+                match menu::pick("jump to (enter: go, q: close)", &items, last, "") {
+                    Some((i, _)) => dirs[i].clone(),
+                    None => return self.redraw(),
+                }
+            }
+        };
+        if dir != self.mid_win.path {
+            self.goto_dir(dir);
+        }
+    }
+
+    // La columna de padres que hay en esa posicion de la pantalla
+    fn window_at(&mut self, y: i32, x: i32) -> Option<&mut MagWindow> {
+        if !self.shows(Pane::Parent) {
+            return None;
+        }
+        std::iter::once(&mut self.parent_win)
+            .chain(&mut self.ancestors)
+            .find(|w| w.encloses(y, x))
+    }
+
+    // Entra en lo que hay en la preview si es un directorio o lo abre en el
+    // paginador si es un fichero
+    fn open_child(&mut self) -> std::io::Result<()> {
+        match self.child_win.dir.is_file() {
+            true => self.handle_open_pager(),
+            false => self.handle_movment_right()?,
+        }
+        Ok(())
+    }
+
+    fn handle_preview_scroll<F: FnOnce(&mut MagWindow)>(&mut self, f: F) {
+        if self.child_win.dir.is_file() {
+            f(&mut self.child_win);
+            werase(self.child_win.win);
+            self.display_child();
+        }
+    }
+
+    fn handle_open_pager(&mut self) {
+        if !self.child_win.dir.is_file() {
+            return;
+        }
+
+        match Pager::new(&self.child_win.path) {
+            Ok(mut pager) => pager.run(),
+            Err(e) => {
+                self.set_message(format!(
+                    "{}: {}",
+                    text::sanitize_os_str(self.child_win.path.as_os_str()),
+                    filesys::io_error_message(&e)
+                ));
+                return;
+            }
+        }
+        self.redraw();
+    }
+
+    fn handle_extract(&mut self, subfolder: bool) {
+        let Some(path) = self.mid_win.selected().map(|e| e.get_path().to_owned()) else {
+            return;
+        };
+        if !archive::is_archive(&path) || vfs::is_virtual(&path) {
+            self.set_message("not an archive");
+            return;
+        }
+
+        let mut dest = self.mid_win.path.clone();
+        let mut conflict = Conflict::Skip;
+
+        if subfolder {
+            dest.push(archive::folder_name(&path));
+            if dest.exists() {
+                let question = format!(
+                    "{} exists: [o]verwrite into it, [s]kip existing files, [r]ename",
+                    text::sanitize_os_str(dest.file_name().unwrap_or_default())
                 );
-            } else {
-                self.child_win.change_dir(
-                    self.mid_win.dir.get_folder().expect("ESTOY AQUI").items[self.mid_win.idx]
-                        .get_path(),
-                    false,
+                match self.ask_conflict(&question) {
+                    Some(Conflict::Rename) => dest = ops::unique_name(&dest),
+                    Some(c) => conflict = c,
+                    None => return,
+                }
+            }
+        } else {
+            let existing = vfs::list(&path)
+                .unwrap_or_default()
+                .iter()
+                .filter(|e| dest.join(e.path.file_name().unwrap_or_default()).exists())
+                .count();
+            if existing > 0 {
+                let question = format!(
+                    "{} entries already exist: [o]verwrite, [s]kip, [r]ename",
+                    existing
                 );
+                match self.ask_conflict(&question) {
+                    Some(c) => conflict = c,
+                    None => return,
+                }
             }
+        }
 
-            wclear(self.child_win.win);
-            self.child_win.display();
-            self.mid_win.display();
+        let name = format!(
+            "extract {}",
+            text::sanitize_os_str(path.file_name().unwrap())
+        );
+        self.jobs.spawn(name, move |p| {
+            archive::extract(&path, &dest, conflict, p).map(|r| r.summary())
+        });
+        self.display_status();
+    }
+
+    fn handle_compress(&mut self) {
+        if vfs::is_virtual(&self.mid_win.path) {
+            self.set_message("read-only: inside an archive");
+            return;
+        }
+        let sources = self.mid_win.selection();
+        if sources.is_empty() {
+            return;
+        }
+
+        let base = self.mid_win.path.clone();
+        let stem = match sources.len() {
+            1 => sources[0].file_name(),
+            _ => base.file_name(),
+        };
+        let default = format!("{}.tar.gz", stem.unwrap_or_default().to_string_lossy());
+
+        let Some(name) = prompt::read_line(stdscr(), LINES() - 1, "compress to: ", &default) else {
+            self.display_status();
+            return;
+        };
+        let mut dest = base.join(name.trim());
+        if archive::Format::of(&dest).is_none() {
+            self.set_message("unknown archive format, use .zip .tar .tar.gz .tar.xz or .tar.zst");
+            return;
+        }
+
+        if dest.exists() {
+            match prompt::ask(
+                stdscr(),
+                LINES() - 1,
+                "file exists: [o]verwrite, [r]ename",
+                "or",
+            ) {
+                Some('o') => {}
+                Some(_) => dest = ops::unique_name(&dest),
+                None => {
+                    self.display_status();
+                    return;
+                }
+            }
+        }
+
+        self.mid_win.marked.clear();
+        self.display_mid();
+
+        let name = format!("compress {}", text::sanitize(name.trim()));
+        self.jobs.spawn(name, move |p| {
+            archive::create(&sources, &base, &dest, p).map(|n| format!("{} files", n))
+        });
+        self.display_status();
+    }
+
+    fn ask_conflict(&mut self, question: &str) -> Option<Conflict> {
+        let answer = prompt::ask(stdscr(), LINES() - 1, question, "osr");
+        self.display_status();
+        answer.and_then(Conflict::from_key)
+    }
+
+    fn set_message<S: Into<String>>(&mut self, msg: S) {
+        self.message = Some(msg.into());
+        self.display_status();
+    }
+
+    // Ultima fila: progreso de los trabajos o el ultimo mensaje
+    fn display_status(&self) {
+        let y = LINES() - 1;
+        let line = self.jobs.status_line().or_else(|| self.message.clone());
+
+        mv(y, 0);
+        clrtoeol();
+        if let Some(line) = line {
+            let width = COLS().max(0) as usize;
+            mvaddstr(y, 1, &text::clip(&line, width.saturating_sub(2)));
+        }
+        refresh();
+    }
+
+    fn handle_movment_down(&mut self) -> std::io::Result<()> {
+        let len = self.mid_win.count();
+        if self.mid_win.idx + 1 < len {
+            self.mid_win.idx += 1;
+            self.update_child();
+        }
+
+        Ok(())
+    }
+
+    fn handle_movment_up(&mut self) -> std::io::Result<()> {
+        if self.mid_win.idx >= 1 {
+            self.mid_win.idx -= 1;
+            self.update_child();
         }
 
         Ok(())
     }
 
+    // Carga en la columna derecha la entrada seleccionada en la del medio
+    fn update_child(&mut self) {
+        match self.mid_win.selected() {
+            Some(entry) => {
+                let (path, browsable) = (entry.get_path().clone(), entry.is_browsable());
+                self.child_win.change_dir(path, browsable);
+            }
+            None => self.child_win.clear(),
+        }
+
+        wclear(self.child_win.win);
+        self.display_child();
+        self.display_mid();
+    }
+
     // TODO: ROOT DIRECTORY DOESENT TURN TO MID WIN
     fn handle_movment_left(&mut self) -> std::io::Result<()> {
         if self.parent_win.path.parent().is_some() {
             std::mem::swap(&mut self.mid_win, &mut self.parent_win);
             std::mem::swap(&mut self.parent_win, &mut self.child_win);
-            self.child_win.idx = 0;
+            self.set_views();
+            // El cursor queda sobre el directorio del que venimos
+            self.mid_win.select(&self.child_win.path);
             self.parent_win
                 .change_dir(self.mid_win.path.parent().unwrap(), true);
-            self.parent_win.fetch();
+            self.parent_win.select(&self.mid_win.path);
+            self.history.visit(&self.mid_win.path);
+            self.track(&self.mid_win.path.clone());
             self.display();
         }
 
@@ -224,63 +1247,296 @@ impl State {
     }
 
     fn handle_movment_right(&mut self) -> std::io::Result<()> {
-        if self.child_win.dir.is_folder() && !self.child_win.dir.is_folder_empty() {
-            self.child_win.idx = 0;
+        // Se puede entrar aunque todavia no haya llegado ninguna entrada
+        let enter = !self.child_win.dir.is_folder_empty() || self.child_win.is_loading();
+        if self.child_win.dir.is_folder() && enter {
             std::mem::swap(&mut self.mid_win, &mut self.child_win);
             std::mem::swap(&mut self.child_win, &mut self.parent_win);
-            self.child_win.change_dir(
-                self.mid_win.dir.get_folder_path(self.mid_win.idx).unwrap(),
-                true,
-            );
-            self.child_win.fetch();
+            // Desde el arbol se puede entrar en un directorio de mas abajo
+            let parent = self.mid_win.path.parent().unwrap_or(&self.mid_win.path);
+            if parent != self.parent_win.path {
+                let parent = parent.to_owned();
+                self.parent_win.change_dir(&parent, true);
+                self.parent_win.select(&self.mid_win.path);
+            }
+            self.set_views();
+            self.update_child();
+            self.history.visit(&self.mid_win.path);
+            self.track(&self.mid_win.path.clone());
             self.display();
         }
 
         Ok(())
     }
 
+    // El orden y los ocultos son para todas las columnas y el resto solo
+    // para la del medio; al moverse las ventanas cambian de columna y de lo que
+    // les toca
+    fn set_views(&mut self) {
+        let (sort, hidden) = (self.view.sort, self.view.hidden);
+        self.parent_win.set_view(sort, None, hidden);
+        self.mid_win
+            .set_view(sort, self.view.filter.as_deref(), hidden);
+        self.child_win.set_view(sort, None, hidden);
+        self.other_win.set_view(sort, None, hidden);
+
+        self.mid_win.set_flat(self.view.flatten);
+        for win in [
+            &mut self.parent_win,
+            &mut self.child_win,
+            &mut self.other_win,
+        ] {
+            win.set_flat(None);
+        }
+
+        for win in [
+            &mut self.parent_win,
+            &mut self.child_win,
+            &mut self.other_win,
+        ] {
+            win.collapse_all();
+        }
+        if !self.view.tree {
+            self.mid_win.collapse_all();
+        }
+    }
+
     fn display(&mut self) {
         //box_(self.child_win.win, 0, 0);
         //box_(self.parent_win.win, 0, 0);
         //box_(self.mid_win.win, 0, 0);
         // clear();
 
+        if self.too_small() {
+            self.display_too_small();
+            return;
+        }
+        self.sync_ancestors();
         self.resize();
-        self.mid_win.display();
-        self.mid_win.display_info(stdscr());
-        self.parent_win.display();
-        self.child_win.display();
+        // Al moverse las ventanas rotan y cada una tiene que ir a su sitio
+        self.layout();
+        self.display_tabs();
+        let layout = self.layout;
+        layout.display(self);
+        self.display_status();
     }
 
-    fn resize(&mut self) {
-        let w = getmaxx(stdscr());
-        let h = getmaxy(stdscr());
+    fn shows(&self, pane: Pane) -> bool {
+        self.layout.shows(self, pane)
+    }
+
+    /// La columna del medio con su cabecera.
+    pub fn display_mid(&self) {
+        if self.shows(Pane::Mid) {
+            self.mid_win.display();
+            self.mid_win.display_info(stdscr());
+        }
+    }
 
-        if w != self.dim.x || h != self.dim.y {
-            let w_right = (w as f32 * W_RIGHT) as i32;
-            let w_middle = (w as f32 * W_MIDDLE) as i32;
-            let w_left = (w as f32 * W_LEFT) as i32;
+    // Las columnas de padres de mas arriba siguen a la del medio en vez de
+    // rotar al moverse: la primera muestra el abuelo, la siguiente el
+    // bisabuelo...
+    fn sync_ancestors(&mut self) {
+        let wanted = self.columns.parents.saturating_sub(1);
+        while self.ancestors.len() > wanted {
+            let win = self.ancestors.pop().unwrap();
+            delwin(win.win);
+        }
+        while self.ancestors.len() < wanted {
+            let mut win = MagWindow::new("", Pos::new(0, 0), Pos::new(1, 1));
+            win.clear();
+            self.ancestors.push(win);
+        }
 
-            self.parent_win
-                .change_dim(Pos::new(1, START_TOP), Pos::new(w_right, h - START_TOP));
-            self.mid_win.change_dim(
-                Pos::new(1 + w_right, START_TOP),
-                Pos::new(w_middle, h - START_TOP),
-            );
-            self.child_win.change_dim(
-                Pos::new(1 + w_right + w_middle, START_TOP),
-                Pos::new(w_left, h - START_TOP),
-            );
+        let mid = self.mid_win.path.clone();
+        let (sort, hidden) = (self.view.sort, self.view.hidden);
+        for (i, win) in self.ancestors.iter_mut().enumerate() {
+            let mut up = mid.ancestors().skip(i + 1);
+            match (up.next(), up.next()) {
+                (Some(child), Some(dir)) => {
+                    if win.path != dir {
+                        win.change_dir(dir, true);
+                    }
+                    win.set_view(sort, None, hidden);
+                    win.select(child);
+                }
+                _ => win.clear(),
+            }
+        }
+    }
+
+    fn handle_columns(&mut self, ch: i32) {
+        let c = &mut self.columns;
+        match ch {
+            //[
+            91 => c.parents = c.parents.saturating_sub(1),
+            //]
+            93 => c.parents = (c.parents + 1).min(MAX_PARENTS),
+            //(
+            40 => c.resize(0, -1),
+            //)
+            41 => c.resize(0, 1),
+            //_
+            95 => c.resize(1, -1),
+            //=
+            61 => c.resize(1, 1),
+            //{
+            123 => c.resize(2, -1),
+            //}
+            125 => c.resize(2, 1),
+            //w
+            119 => c.preview = !c.preview,
+            //W
+            87 => c.maximized = !c.maximized,
+            _ => return,
+        }
+        let msg = format!(
+            "columns: {} parents, ratios {}:{}:{}, preview {}",
+            c.parents,
+            c.ratios[0],
+            c.ratios[1],
+            c.ratios[2],
+            if c.maximized {
+                "maximized"
+            } else if c.preview {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        self.sync_ancestors();
+        self.layout();
+        self.update_child();
+        self.redraw();
+        self.set_message(msg);
+    }
+
+    // Primera fila: las pestañas, si hay mas de una
+    fn display_tabs(&self) {
+        mv(0, 0);
+        clrtoeol();
+        if self.tabs.len() < 2 {
+            return;
+        }
+
+        let width = COLS().max(0) as usize;
+        let mut x = 1;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let path = match tab {
+                Some(t) => &t.mid_win.path,
+                None => &self.mid_win.path,
+            };
+            let label = format!(" {} {} ", i + 1, Tab::title(path));
+            let label = text::clip(&label, width.saturating_sub(x + 1));
+            if label.is_empty() {
+                break;
+            }
+            let attr = match tab.is_none() {
+                true => COLOR_PAIR(5) | A_BOLD(),
+                false => A_NORMAL(),
+            };
+            attron(attr);
+            mvaddstr(0, x as i32, &label);
+            attroff(attr);
+            x += text::width(&label) + 1;
+        }
+        refresh();
+    }
+
+    pub fn display_child(&self) {
+        if !self.shows(Pane::Child) {
+            return;
+        }
+        self.child_win.display_preview(self.child_details());
+    }
+
+    fn child_details(&self) -> bool {
+        // La lista de un archivo comprimido siempre lleva tamaños y fechas
+        self.dir_details || archive::is_archive(&self.child_win.path)
+    }
+
+    // Repinta todo despues de que algo haya usado la pantalla completa
+    fn redraw(&mut self) {
+        clear();
+        refresh();
+        let wins = [
+            &self.parent_win,
+            &self.mid_win,
+            &self.child_win,
+            &self.other_win,
+        ];
+        for win in wins.into_iter().chain(&self.ancestors) {
+            werase(win.win);
+        }
+        self.display();
+    }
+
+    // Si la pantalla ha cambiado de tamaño se borra todo lo pintado
+    fn resize(&mut self) {
+        let dim = Pos::new(getmaxx(stdscr()), getmaxy(stdscr()));
+        if dim != self.dim {
+            self.dim = dim;
             clear();
             refresh();
-            wrefresh(self.mid_win.win);
         }
     }
 
+    // ncurses recibe el SIGWINCH y lo convierte en KEY_RESIZE
+    fn handle_resize(&mut self) {
+        if self.too_small() {
+            self.display_too_small();
+            return;
+        }
+        self.resize();
+        self.layout();
+        // La preview de un fichero se lee con el tamaño de la ventana
+        if self.child_win.dir.is_file() {
+            self.child_win.fetch();
+        }
+        self.redraw();
+    }
+
+    fn too_small(&self) -> bool {
+        COLS() < MIN_WIDTH || LINES() < MIN_HEIGHT
+    }
+
+    fn display_too_small(&self) {
+        clear();
+        let width = COLS().max(0) as usize;
+        let lines = [
+            "terminal too small".to_string(),
+            format!("{}x{} < {}x{}", COLS(), LINES(), MIN_WIDTH, MIN_HEIGHT),
+        ];
+        for (y, line) in lines.iter().enumerate() {
+            mvaddstr(y as i32, 0, &text::clip(line, width));
+        }
+        refresh();
+    }
+
+    // Coloca las ventanas segun el tamaño de la pantalla
+    fn layout(&mut self) {
+        if self.too_small() {
+            return;
+        }
+        let w = getmaxx(stdscr());
+        let h = getmaxy(stdscr());
+        let layout = self.layout;
+        layout.place(self, w, h);
+    }
+
     pub fn exit(&mut self) {
+        self.history.flush();
+        self.frecency.flush();
+        for tab in self.tabs.drain(..).flatten() {
+            tab.close();
+        }
+        for win in self.ancestors.drain(..) {
+            delwin(win.win);
+        }
         delwin(self.child_win.win);
         delwin(self.mid_win.win);
         delwin(self.parent_win.win);
+        delwin(self.other_win.win);
         endwin();
     }
 }
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use ncurses::delwin;
+
+use crate::{config, filesys::Sort, pos::Pos, text, ui::MagWindow};
+
+/// Como se ve el listado de una pestaña.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub sort: Sort,
+    /// Solo se aplica a la columna del medio.
+    pub filter: Option<String>,
+    /// La columna del medio es un arbol en el que se pueden desplegar los
+    /// directorios.
+    pub tree: bool,
+    /// Mostrar lo que empieza por punto.
+    pub hidden: bool,
+    /// Profundidad hasta la que se listan todos los ficheros de debajo en
+    /// la columna del medio, con su ruta. `None` es el listado normal.
+    pub flatten: Option<usize>,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            sort: Sort::default(),
+            filter: None,
+            tree: false,
+            hidden: config::get().show_hidden,
+            flatten: None,
+        }
+    }
+}
+
+/// Las ventanas de una pestaña con sus ajustes. La pestaña activa vive
+/// en los campos de `State` y se intercambia con una de estas al cambiar.
+#[derive(Debug)]
+pub struct Tab {
+    pub parent_win: MagWindow,
+    pub mid_win: MagWindow,
+    pub child_win: MagWindow,
+    pub other_win: MagWindow,
+    pub active_right: bool,
+    pub view: View,
+}
+
+impl Tab {
+    /// Abre `path` en la columna del medio. Las ventanas se crean con el
+    /// tamaño minimo; hay que colocarlas antes de pintarlas.
+    pub fn open(path: &Path) -> Self {
+        let (coord, dim) = (Pos::new(0, 0), Pos::new(1, 1));
+
+        let mut parent_win = MagWindow::new(path.parent().unwrap_or(path), coord, dim);
+        parent_win.fetch();
+        parent_win.select(path);
+
+        let mut mid_win = MagWindow::new(path, coord, dim);
+        mid_win.fetch();
+
+        // Se rellena cuando lleguen las primeras entradas del medio
+        let mut child_win = MagWindow::new("", coord, dim);
+        child_win.clear();
+
+        // Con dos paneles se abre al colocarlo
+        let mut other_win = MagWindow::new("", coord, dim);
+        other_win.clear();
+
+        Self {
+            parent_win,
+            mid_win,
+            child_win,
+            other_win,
+            active_right: false,
+            view: View::default(),
+        }
+    }
+
+    /// Nombre corto para la barra de pestañas, ya listo para mostrar.
+    pub fn title(path: &Path) -> String {
+        match path.file_name() {
+            Some(name) => text::sanitize_os_str(name),
+            None => text::sanitize_os_str(path.as_os_str()),
+        }
+    }
+
+    pub fn close(self) {
+        delwin(self.parent_win.win);
+        delwin(self.mid_win.win);
+        delwin(self.child_win.win);
+        delwin(self.other_win.win);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::config;
+
+const ELLIPSIS: char = '…';
+
+/// Convierte una linea de un fichero en algo seguro para `mvwaddstr`: los
+/// tabuladores se expanden hasta la siguiente parada y el resto de caracteres
+/// de control se muestran de forma visible (`^[`, `\r`...).
+pub fn sanitize_line(line: &str) -> String {
+    let tab_width = config::get().tab_width;
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = tab_width - col % tab_width;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            col += spaces;
+        } else {
+            col += push_visible(&mut out, c);
+        }
+    }
+
+    out
+}
+
+/// Como `sanitize_line`, pero para nombres y rutas: aqui un tabulador tambien
+/// se muestra escapado para que no se confunda con espacios, y `\` se dobla
+/// para que un nombre con `\xff` escrito no se vea igual que uno con ese byte.
+pub fn sanitize(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            c => {
+                push_visible(&mut out, c);
+            }
+        }
+    }
+    out
+}
+
+/// Nombre de fichero listo para mostrar. Los bytes que no son UTF-8 valido se
+/// muestran como `\xNN` en vez de `U+FFFD`, asi dos nombres distintos nunca se
+/// ven iguales.
+pub fn sanitize_os_str(s: &OsStr) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut bytes = s.as_bytes();
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                out.push_str(&sanitize(valid));
+                break;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                out.push_str(&sanitize(std::str::from_utf8(valid).unwrap_or_default()));
+
+                let bad = e.error_len().unwrap_or(rest.len());
+                for b in &rest[..bad] {
+                    out.push_str(&format!("\\x{:02x}", b));
+                }
+                bytes = &rest[bad..];
+            }
+        }
+    }
+
+    out
+}
+
+// Devuelve el numero de columnas que ocupa lo que se ha escrito.
+fn push_visible(out: &mut String, c: char) -> usize {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\x7f' => out.push_str("^?"),
+        c if (c as u32) < 0x20 => {
+            out.push('^');
+            out.push((c as u8 + b'@') as char);
+        }
+        c if ('\u{80}'..='\u{9f}').contains(&c) => {
+            let s = format!("\\u{{{:x}}}", c as u32);
+            out.push_str(&s);
+            return s.len();
+        }
+        c => {
+            out.push(c);
+            return c.width().unwrap_or(0);
+        }
+    }
+    2
+}
+
+/// Columnas de terminal que ocupa `s` (los caracteres CJK y muchos emoji
+/// ocupan dos).
+pub fn width(s: &str) -> usize {
+    s.width()
+}
+
+/// Corta `s` para que no ocupe mas de `max` columnas.
+pub fn clip(s: &str, max: usize) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut w = 0;
+
+    for c in s.chars() {
+        let cw = c.width().unwrap_or(0);
+        if w + cw > max {
+            break;
+        }
+        w += cw;
+        out.push(c);
+    }
+
+    out
+}
+
+/// Parte `s` en trozos de como mucho `max` columnas. Una cadena vacia
+/// devuelve un unico trozo vacio para que las lineas en blanco sigan ocupando
+/// una fila.
+pub fn wrap(s: &str, max: usize) -> Vec<String> {
+    let mut out = vec![String::new()];
+    let mut w = 0;
+
+    for c in s.chars() {
+        let cw = c.width().unwrap_or(0);
+        if w + cw > max.max(1) && w > 0 {
+            out.push(String::new());
+            w = 0;
+        }
+        w += cw;
+        out.last_mut().unwrap().push(c);
+    }
+
+    out
+}
+
+/// Acorta un nombre de fichero a `max` columnas poniendo `…` en medio pero
+/// manteniendo visible la extension: `un_nombre_largo.tar.gz` -> `un_nom….gz`.
+pub fn truncate_name(name: &str, max: usize) -> String {
+    if width(name) <= max {
+        return name.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+
+    let ext = match name.rfind('.') {
+        Some(i) if i > 0 => &name[i..],
+        _ => "",
+    };
+    // Si la extension no deja sitio para el nombre no merece la pena guardarla
+    let ext = if width(ext) + 2 <= max / 2 + 1 {
+        ext
+    } else {
+        ""
+    };
+
+    let stem = &name[..name.len() - ext.len()];
+    let mut out = clip(stem, max - 1 - width(ext));
+    out.push(ELLIPSIS);
+    out.push_str(ext);
+    out
+}
+
+/// Rellena con espacios a la derecha hasta `w` columnas.
+pub fn pad_right(s: &str, w: usize) -> String {
+    let mut out = s.to_string();
+    out.extend(std::iter::repeat_n(' ', w.saturating_sub(width(s))));
+    out
+}
+
+/// Tamaño en formato corto, como `ls -h`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else if size < 10.0 {
+        format!("{:.1}{}", size, UNITS[unit])
+    } else {
+        format!("{:.0}{}", size, UNITS[unit])
+    }
+}
+
+/// Fecha de modificacion en hora local, siempre 16 columnas.
+pub fn format_time(t: SystemTime) -> String {
+    DateTime::<Local>::from(t)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    #[test]
+    fn control_chars() {
+        assert_eq!(sanitize("a\x1b[31mb"), "a^[[31mb");
+        assert_eq!(sanitize("line\nbreak\t"), "line\\nbreak\\t");
+        assert_eq!(sanitize("\x7f\u{9b}"), "^?\\u{9b}");
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(
+            sanitize_line("\tx"),
+            " ".repeat(config::get().tab_width) + "x"
+        );
+        assert_eq!(
+            sanitize_line("ab\tx"),
+            "ab".to_string() + &" ".repeat(config::get().tab_width - 2) + "x"
+        );
+    }
+
+    #[test]
+    fn wide_chars() {
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(clip("日本語", 5), "日本");
+        assert_eq!(pad_right("日本", 6), "日本  ");
+        assert_eq!(
+            sanitize_line("日\tx"),
+            "日".to_string() + &" ".repeat(config::get().tab_width - 2) + "x"
+        );
+    }
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(wrap("", 4), vec![""]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("日本語", 3), vec!["日", "本", "語"]);
+    }
+
+    #[test]
+    fn truncate() {
+        assert_eq!(truncate_name("corto.rs", 10), "corto.rs");
+        assert_eq!(truncate_name("un_nombre_largo.tar.gz", 12), "un_nombr….gz");
+        assert_eq!(width(&truncate_name("un_nombre_largo.tar.gz", 12)), 12);
+        assert_eq!(truncate_name(".bashrc_muy_largo", 8), ".bashrc…");
+        assert_eq!(truncate_name("日本語のファイル.txt", 10), "日本….txt");
+        assert!(width(&truncate_name("日本語のファイル.txt", 10)) <= 10);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(20 * 1024 * 1024), "20M");
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let a = OsString::from_vec(vec![b'a', 0xff, b'b']);
+        let b = OsString::from_vec(vec![b'a', 0xfe, b'b']);
+        assert_eq!(sanitize_os_str(&a), "a\\xffb");
+        assert_ne!(sanitize_os_str(&a), sanitize_os_str(&b));
+
+        // El texto `\xff` no se confunde con el byte
+        let text = OsString::from("a\\xffb");
+        assert_eq!(sanitize_os_str(&text), "a\\\\xffb");
+        assert_ne!(sanitize_os_str(&text), sanitize_os_str(&a));
+    }
+}
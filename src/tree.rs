@@ -0,0 +1,349 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    filesys::{self, MagEntry, MagFolder, Sort},
+    loader::{self, Load, Msg},
+};
+
+/// Directorios desplegados de la vista de arbol y sus filas ya aplanadas.
+/// Las filas se guardan para no recorrer el arbol en cada tecla, asi que hay
+/// que llamar a `rebuild` cada vez que cambian las entradas de la raiz.
+#[derive(Debug, Default)]
+pub struct Tree {
+    expanded: BTreeSet<PathBuf>,
+    rows: Vec<Row>,
+    // Directorios desplegados que se estan leyendo, con lo que ya ha llegado
+    loads: HashMap<PathBuf, (Load, Vec<MagEntry>)>,
+}
+
+// Una fila: los indices para llegar a la entrada desde la raiz y las guias
+// que van delante
+#[derive(Debug)]
+struct Row {
+    at: Vec<usize>,
+    guide: String,
+}
+
+impl Tree {
+    /// `true` si no hay nada desplegado y la lista es la plana.
+    pub fn is_empty(&self) -> bool {
+        self.expanded.is_empty()
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    pub fn clear(&mut self) {
+        self.expanded.clear();
+        self.rows.clear();
+        self.loads.clear();
+    }
+
+    pub fn count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Las filas de `skip` a `skip + take`, con sus guias.
+    pub fn rows<'a>(
+        &'a self,
+        root: &'a MagFolder,
+        skip: usize,
+        take: usize,
+    ) -> impl Iterator<Item = (&'a MagEntry, &'a str)> {
+        self.rows
+            .iter()
+            .skip(skip)
+            .take(take)
+            .filter_map(|r| Some((entry_at(root, &r.at)?, r.guide.as_str())))
+    }
+
+    pub fn position(&self, root: &MagFolder, path: &Path) -> Option<usize> {
+        self.rows(root, 0, usize::MAX)
+            .position(|(e, _)| e.get_path() == path)
+    }
+
+    /// Vuelve a aplanar el arbol despues de cambiar las entradas de `root`.
+    pub fn rebuild(&mut self, root: &MagFolder) {
+        self.rows.clear();
+        if !self.expanded.is_empty() {
+            self.push_rows(&root.items, None, &mut Vec::new());
+        }
+    }
+
+    fn push_rows(&mut self, items: &[MagEntry], indent: Option<&str>, at: &mut Vec<usize>) {
+        for (i, entry) in items.iter().enumerate() {
+            let last = i + 1 == items.len();
+            let guide = match indent {
+                None => String::new(),
+                Some(indent) => format!("{}{}", indent, if last { "└ " } else { "├ " }),
+            };
+            at.push(i);
+            self.rows.push(Row {
+                at: at.clone(),
+                guide,
+            });
+
+            if let MagEntry::Dir(sub) = entry {
+                if self.expanded.contains(&sub.data.path) {
+                    let inner = match indent {
+                        None => String::new(),
+                        Some(indent) => format!("{}{}", indent, if last { "  " } else { "│ " }),
+                    };
+                    self.push_rows(&sub.items, Some(&inner), at);
+                }
+            }
+            at.pop();
+        }
+    }
+
+    /// Despliega el directorio `path` de `root`. Sus entradas se leen en
+    /// segundo plano y llegan con `poll`.
+    pub fn expand(&mut self, root: &MagFolder, path: PathBuf) {
+        self.expanded.insert(path.clone());
+        self.load(root, path);
+        self.rebuild(root);
+    }
+
+    /// Pliega `selected` o, si no esta desplegado, el directorio que lo
+    /// contiene. Devuelve el que se ha plegado, para poner alli el cursor.
+    pub fn collapse(&mut self, root: &mut MagFolder, selected: &Path) -> Option<PathBuf> {
+        let path = match self.expanded.contains(selected) {
+            true => selected.to_owned(),
+            false => selected
+                .parent()
+                .filter(|p| self.expanded.contains(*p))?
+                .to_owned(),
+        };
+
+        self.forget(&path);
+        if let Some(MagEntry::Dir(sub)) = find_mut(root, &path) {
+            sub.items.clear();
+        }
+        self.rebuild(root);
+        Some(path)
+    }
+
+    /// Pliega todo. Devuelve la entrada de arriba del todo que contenia
+    /// `selected`, para poner alli el cursor.
+    pub fn collapse_all(
+        &mut self,
+        root: &mut MagFolder,
+        selected: Option<&Path>,
+    ) -> Option<PathBuf> {
+        let top = selected.and_then(|p| {
+            root.items
+                .iter()
+                .map(MagEntry::get_path)
+                .find(|e| p.starts_with(e))
+                .cloned()
+        });
+        for path in std::mem::take(&mut self.expanded) {
+            if let Some(MagEntry::Dir(sub)) = find_mut(root, &path) {
+                sub.items.clear();
+            }
+        }
+        self.clear();
+        top
+    }
+
+    /// Vuelve a leer los directorios desplegados despues de recargar `root`.
+    /// Se empieza por los de arriba; los de dentro se leen cuando llega su
+    /// padre, y los que ya no estan se olvidan.
+    pub fn refill(&mut self, root: &MagFolder) {
+        self.loads.clear();
+        let top: Vec<PathBuf> = self
+            .expanded
+            .iter()
+            .filter(|p| {
+                !p.parent()
+                    .is_some_and(|parent| self.expanded.contains(parent))
+            })
+            .cloned()
+            .collect();
+        for path in top {
+            self.load(root, path);
+        }
+        self.rebuild(root);
+    }
+
+    /// Recoge las entradas de los directorios que se estan leyendo.
+    /// Devuelve `true` si ha cambiado algo.
+    pub fn poll(&mut self, root: &mut MagFolder, sort: Sort, hidden: bool) -> bool {
+        let mut done = Vec::new();
+        for (path, (load, entries)) in &mut self.loads {
+            for msg in load.try_iter() {
+                match msg {
+                    Msg::Entries(batch) => {
+                        entries.extend(batch.into_iter().map(MagEntry::from_entry))
+                    }
+                    Msg::Done(result) => done.push((path.clone(), result)),
+                }
+            }
+        }
+        if done.is_empty() {
+            return false;
+        }
+
+        for (path, result) in done {
+            let Some((_, entries)) = self.loads.remove(&path) else {
+                continue;
+            };
+            let Some(MagEntry::Dir(sub)) = find_mut(root, &path) else {
+                self.forget(&path);
+                continue;
+            };
+            sub.items = entries;
+            sub.error = result.err().map(|e| filesys::io_error_message(&e));
+            sub.arrange(sort, None, hidden);
+
+            let inner: Vec<PathBuf> = self
+                .expanded
+                .iter()
+                .filter(|p| p.parent() == Some(&path))
+                .cloned()
+                .collect();
+            for p in inner {
+                self.load(root, p);
+            }
+        }
+        self.rebuild(root);
+        true
+    }
+
+    // Empieza a leer `path` si sigue en `root`; si no, lo olvida
+    fn load(&mut self, root: &MagFolder, path: PathBuf) {
+        match find(root, &path) {
+            Some(MagEntry::Dir(_)) => {
+                let load = loader::load(&path);
+                self.loads.insert(path, (load, Vec::new()));
+            }
+            _ => self.forget(&path),
+        }
+    }
+
+    // Quita `path` y todo lo desplegado debajo
+    fn forget(&mut self, path: &Path) {
+        self.expanded.retain(|p| !p.starts_with(path));
+        self.loads.retain(|p, _| !p.starts_with(path));
+    }
+}
+
+fn entry_at<'a>(root: &'a MagFolder, at: &[usize]) -> Option<&'a MagEntry> {
+    let (last, parents) = at.split_last()?;
+    let mut items = &root.items;
+    for &i in parents {
+        match items.get(i)? {
+            MagEntry::Dir(sub) => items = &sub.items,
+            MagEntry::File(_) => return None,
+        }
+    }
+    items.get(*last)
+}
+
+fn find<'a>(root: &'a MagFolder, path: &Path) -> Option<&'a MagEntry> {
+    let mut items = &root.items;
+    loop {
+        let entry = items.iter().find(|e| path.starts_with(e.get_path()))?;
+        if entry.get_path() == path {
+            return Some(entry);
+        }
+        match entry {
+            MagEntry::Dir(sub) => items = &sub.items,
+            MagEntry::File(_) => return None,
+        }
+    }
+}
+
+// La entrada `path` en cualquier nivel desplegado por debajo de `root`
+fn find_mut<'a>(root: &'a mut MagFolder, path: &Path) -> Option<&'a mut MagEntry> {
+    let mut items = &mut root.items;
+    loop {
+        let entry = items.iter_mut().find(|e| path.starts_with(e.get_path()))?;
+        if entry.get_path() == path {
+            return Some(entry);
+        }
+        match entry {
+            MagEntry::Dir(sub) => items = &mut sub.items,
+            MagEntry::File(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memfs::MemoryFs, vfs};
+    use std::{sync::Arc, thread::sleep, time::Duration};
+
+    fn wait(tree: &mut Tree, root: &mut MagFolder) {
+        for _ in 0..200 {
+            tree.poll(root, Sort::Name, true);
+            if tree.loads.is_empty() {
+                return;
+            }
+            sleep(Duration::from_millis(5));
+        }
+        panic!("the tree never finished loading");
+    }
+
+    fn lines(tree: &Tree, root: &MagFolder) -> Vec<String> {
+        tree.rows(root, 0, usize::MAX)
+            .map(|(e, guide)| {
+                let name = e.get_path().file_name().unwrap().to_string_lossy();
+                format!("{}{}", guide, name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expand_and_collapse() {
+        let dir = PathBuf::from("/mag-tree-test");
+        let memfs = Arc::new(MemoryFs::new());
+        memfs.add_file(dir.join("a/x/hondo.txt"), "");
+        memfs.add_file(dir.join("a/y.txt"), "");
+        memfs.add_file(dir.join("b.txt"), "");
+        vfs::mount(&dir, memfs);
+
+        let mut root = MagFolder::new(&dir).get_entries_return().unwrap();
+        let mut tree = Tree::default();
+        tree.expand(&root, dir.join("a"));
+        wait(&mut tree, &mut root);
+        tree.expand(&root, dir.join("a/x"));
+        wait(&mut tree, &mut root);
+        assert_eq!(
+            lines(&tree, &root),
+            ["a", "├ x", "│ └ hondo.txt", "└ y.txt", "b.txt"]
+        );
+        assert_eq!(tree.position(&root, &dir.join("a/y.txt")), Some(3));
+
+        assert!(find_mut(&mut root, &dir.join("a/x/hondo.txt")).is_some());
+        assert!(find_mut(&mut root, &dir.join("nada")).is_none());
+        assert!(find_mut(&mut root, &dir.join("b.txt/nada")).is_none());
+
+        // Al recargar la raiz se vuelven a leer, tambien los de dentro
+        let mut root = MagFolder::new(&dir).get_entries_return().unwrap();
+        tree.refill(&root);
+        wait(&mut tree, &mut root);
+        assert_eq!(tree.count(), 5);
+
+        // Desde una entrada de dentro se pliega su directorio
+        let folded = tree.collapse(&mut root, &dir.join("a/x/hondo.txt"));
+        assert_eq!(folded, Some(dir.join("a/x")));
+        assert_eq!(lines(&tree, &root), ["a", "├ x", "└ y.txt", "b.txt"]);
+        assert_eq!(tree.collapse(&mut root, &dir.join("b.txt")), None);
+
+        // Plegarlo todo deja el cursor en la entrada de arriba
+        tree.expand(&root, dir.join("a/x"));
+        wait(&mut tree, &mut root);
+        let top = tree.collapse_all(&mut root, Some(&dir.join("a/x/hondo.txt")));
+        assert_eq!(top, Some(dir.join("a")));
+        assert!(tree.is_empty());
+        assert_eq!(tree.count(), 0);
+
+        vfs::unmount(&dir);
+    }
+}
@@ -1,15 +1,26 @@
 use std::{
+    cell::Cell,
+    collections::BTreeSet,
     path::{Path, PathBuf},
-    usize,
 };
 
 use ncurses::*;
 
 use crate::{
-    filesys::{MagEntry, MagFile, MagFolder},
+    breadcrumb::{self, Segment},
+    cache,
+    filesys::{self, MagEntry, MagFile, MagFolder, MagItem, Sort},
+    loader::{self, Msg},
     pos::Pos,
+    text,
+    tree::Tree,
 };
 
+/// Fila de la pantalla de la cabecera de `display_info`.
+pub const INFO_Y: i32 = 1;
+// Columna donde empieza la ruta
+const INFO_X: i32 = 3;
+
 #[derive(Debug)]
 pub struct MagWindow {
     pub path: PathBuf,
@@ -19,6 +30,40 @@ pub struct MagWindow {
     pub coord: Pos<i32>,
     pub my_pos: Pos<i32>,
     pub dir: MagEntry,
+    /// Entradas marcadas con espacio para operar sobre varias a la vez.
+    pub marked: BTreeSet<PathBuf>,
+    /// Lectura del directorio en segundo plano, mientras dure.
+    loading: Option<Loading>,
+    // Primera entrada visible de la lista.
+    top: Cell<usize>,
+    // Entrada que hay que seleccionar en cuanto aparezca en la lista.
+    want: Option<PathBuf>,
+    sort: Sort,
+    // Solo se muestran las entradas cuyo nombre lo contiene.
+    filter: Option<String>,
+    // Mostrar las entradas que empiezan por punto.
+    hidden: bool,
+    // Profundidad si la lista son todos los ficheros de debajo en vez del
+    // contenido del directorio.
+    flat: Option<usize>,
+    // Directorios desplegados en la vista de arbol. Vacio es la lista plana.
+    tree: Tree,
+}
+
+/// Una fila de la lista tal y como se ve. En el arbol las entradas de los
+/// directorios desplegados van debajo de ellos con las guias delante.
+struct Row<'a> {
+    entry: &'a MagEntry,
+    guide: &'a str,
+}
+
+#[derive(Debug)]
+struct Loading {
+    load: loader::Load,
+    // Al recargar un directorio que ya se esta mostrando las entradas nuevas
+    // se guardan aqui hasta que estan todas, para no vaciar la lista. Si es
+    // `None` se van añadiendo a la lista segun llegan.
+    fresh: Option<Vec<MagEntry>>,
 }
 
 impl MagWindow {
@@ -38,6 +83,15 @@ impl MagWindow {
             dir,
             my_pos,
             win,
+            marked: BTreeSet::new(),
+            loading: None,
+            top: Cell::new(0),
+            want: None,
+            sort: Sort::default(),
+            filter: None,
+            hidden: true,
+            flat: None,
+            tree: Tree::default(),
         }
     }
 
@@ -57,51 +111,83 @@ impl MagWindow {
             dir,
             my_pos,
             win,
+            marked: BTreeSet::new(),
+            loading: None,
+            top: Cell::new(0),
+            want: None,
+            sort: Sort::default(),
+            filter: None,
+            hidden: true,
+            flat: None,
+            tree: Tree::default(),
         }
     }
 
-    // TODO: VERY VERBOSE FUNCION
     pub fn display(&self) {
+        self.display_with(false);
+    }
+
+    /// Como `display`, pero si la ventana es un directorio y `details` esta
+    /// activo se muestra un resumen y columnas de tamaño y fecha. Se usa para
+    /// la columna de preview.
+    pub fn display_preview(&self, details: bool) {
+        self.display_with(details);
+    }
+
+    fn display_with(&self, details: bool) {
         match &self.dir {
             MagEntry::Dir(d) => {
-                for (c, i) in d.items.iter().enumerate() {
-                    if c as i32 > self.dimensions.y - 5 {
-                        break;
-                    }
+                if d.items.is_empty() {
+                    let msg = match (&self.loading, &d.error) {
+                        (Some(_), _) => "Loading…",
+                        (None, Some(e)) => e,
+                        (None, None) if self.path.as_os_str().is_empty() => "",
+                        (None, None) => "Empty directory",
+                    };
+                    wattron(self.win, A_BOLD());
+                    mvwaddstr(self.win, 1, 2, msg);
+                    wattroff(self.win, A_BOLD());
+                    wrefresh(self.win);
+                    return;
+                }
 
-                    if c == self.idx {
-                        // Activar formato bold
-                        wattron(self.win, COLOR_PAIR(5) | A_BOLD());
-                        match i {
-                            MagEntry::File(f) => {
-                                mvwprintw(self.win, c as i32 + 1, 2, &f.data.file_name());
-                            }
-                            MagEntry::Dir(f) => {
-                                mvwprintw(self.win, c as i32 + 1, 2, &f.data.file_name());
-                            }
-                        }
-                        wattroff(self.win, COLOR_PAIR(5) | A_BOLD());
-                    } else {
-                        match i {
-                            MagEntry::File(f) => {
-                                mvwprintw(self.win, c as i32 + 1, 2, &f.data.file_name());
-                            }
-                            MagEntry::Dir(f) => {
-                                wattron(self.win, COLOR_PAIR(4));
-                                mvwprintw(self.win, c as i32 + 1, 2, &f.data.file_name());
-                                wattroff(self.win, COLOR_PAIR(4));
-                            }
-                        }
+                if self.loading.is_some() {
+                    let s = format!("loading… {}", d.items.len());
+                    let x = (self.dimensions.x - 1 - text::width(&s) as i32).max(1);
+                    wattron(self.win, A_DIM());
+                    mvwaddstr(self.win, 0, x, &s);
+                    wattroff(self.win, A_DIM());
+                }
+
+                if details {
+                    let summary = d.summary();
+                    let mut line = format!("{} dirs, {} files", summary.dirs, summary.files);
+                    if summary.files > 0 {
+                        line.push_str(&format!(", {}", text::human_size(summary.total_size)));
+                    }
+                    if let Some(newest) = summary.newest {
+                        line.push_str(&format!(", newest {}", text::format_time(newest)));
                     }
+
+                    let width = (self.dimensions.x - 3).max(0) as usize;
+                    wattron(self.win, A_DIM());
+                    mvwaddstr(self.win, 1, 2, &text::clip(&line, width));
+                    wattroff(self.win, A_DIM());
+                    self.display_entries(3, true);
+                } else {
+                    self.display_entries(1, false);
                 }
             }
             MagEntry::File(f) => {
-                if f.content.is_empty() {
-                    mvwprintw(self.win, 1, 1, "Empty File");
+                let width = (self.dimensions.x - 2).max(0) as usize;
+
+                if f.is_empty() {
+                    mvwaddstr(self.win, 1, 1, "Empty File");
                 } else {
-                    let v: Vec<&str> = f.content.split('\n').collect();
-                    for (i, st) in v.iter().enumerate() {
-                        mvwprintw(self.win, i as i32 + 2, 1, st);
+                    let rows = filesys::preview_rows(self.dimensions);
+                    for (i, st) in f.lines.iter().skip(f.scroll).take(rows).enumerate() {
+                        let st = text::sanitize_line(st.strip_suffix('\r').unwrap_or(st));
+                        mvwaddstr(self.win, i as i32 + 2, 1, &text::clip(&st, width));
                     }
                 }
             }
@@ -109,34 +195,275 @@ impl MagWindow {
         wrefresh(self.win);
     }
 
+    fn display_entries(&self, first_row: i32, details: bool) {
+        // Columna 2 a la izquierda y una de margen a la derecha
+        let width = (self.dimensions.x - 3).max(0) as usize;
+
+        let rows = self.list_rows(first_row);
+        let top = self.first_visible(rows);
+
+        for (c, r) in self.rows(top, rows).iter().enumerate() {
+            let (c, i) = (c + top, r.entry);
+            let row = (c - top) as i32 + first_row;
+            let attr = match (c == self.idx, i.is_folder()) {
+                // Activar formato bold
+                (true, _) => COLOR_PAIR(5) | A_BOLD(),
+                (false, true) => COLOR_PAIR(4),
+                (false, false) => A_NORMAL(),
+            };
+
+            let marker = match self.marked.contains(i.get_path()) {
+                true => "*",
+                false => " ",
+            };
+            mvwaddstr(self.win, row, 1, marker);
+
+            let guide = text::clip(r.guide, width);
+            let indent = text::width(&guide);
+            wattron(self.win, A_DIM());
+            mvwaddstr(self.win, row, 2, &guide);
+            wattroff(self.win, A_DIM());
+
+            wattron(self.win, attr);
+            let x = 2 + indent as i32;
+            let name = self.entry_name(i);
+            mvwaddstr(
+                self.win,
+                row,
+                x,
+                &entry_row(i, name, width - indent, details),
+            );
+            wattroff(self.win, attr);
+        }
+    }
+
+    // En la lista aplanada se ve la ruta desde el directorio
+    fn entry_name(&self, entry: &MagEntry) -> String {
+        let path = entry.get_path();
+        match (self.flat, path.strip_prefix(&self.path)) {
+            (Some(_), Ok(rel)) => text::sanitize_os_str(rel.as_os_str()),
+            _ => match path.file_name() {
+                Some(s) => text::sanitize_os_str(s),
+                None => String::from("CORRUPTED FILE NAME"),
+            },
+        }
+    }
+
+    // Filas de la lista si empieza en `first_row`
+    fn list_rows(&self, first_row: i32) -> usize {
+        (self.dimensions.y - 3 - first_row).max(1) as usize
+    }
+
+    /// Si la posicion de pantalla `y`, `x` cae dentro de la ventana.
+    pub fn encloses(&self, y: i32, x: i32) -> bool {
+        wenclose(self.win, y, x)
+    }
+
+    /// Fila de la lista que se ve en la fila `y` de la pantalla. `details`
+    /// como en `display_preview`.
+    pub fn entry_at(&self, y: i32, details: bool) -> Option<usize> {
+        if !self.dir.is_folder() {
+            return None;
+        }
+        let first_row = if details { 3 } else { 1 };
+        let row = y - self.coord.y - first_row;
+        if row < 0 || row as usize >= self.list_rows(first_row) {
+            return None;
+        }
+        let idx = self.top.get() + row as usize;
+        (idx < self.count()).then_some(idx)
+    }
+
+    /// Mueve el cursor `delta` filas sin salirse de la lista.
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.count().saturating_sub(1);
+        self.idx = self.idx.saturating_add_signed(delta).min(last);
+    }
+
+    // Desplaza la lista lo justo para que el cursor quede a la vista
+    fn first_visible(&self, rows: usize) -> usize {
+        let len = self.count();
+        let mut top = self.top.get();
+        if self.idx < top {
+            top = self.idx;
+        } else if self.idx >= top + rows {
+            top = self.idx + 1 - rows;
+        }
+        top = top.min(len.saturating_sub(rows));
+        self.top.set(top);
+        top
+    }
+
+    /// La cabecera: la ruta, lo seleccionado y como se esta viendo la lista.
     pub fn display_info(&self, std: WINDOW) {
-        let s = format!(
-            "{}{}",
-            &self.path.to_string_lossy(),
-            self.dir.get_folder().unwrap().items[self.idx]
-                .get_path()
-                .to_string_lossy()
-        );
-
-        mvwprintw(std, 1, 3, &s);
+        self.display_header(std, false);
+    }
+
+    /// Como `display_info`, con la tecla para saltar a cada directorio de la
+    /// ruta encima de su nombre.
+    pub fn display_hints(&self, std: WINDOW) {
+        self.display_header(std, true);
+    }
+
+    fn display_header(&self, std: WINDOW, hints: bool) {
+        wmove(std, INFO_Y, 0);
+        wclrtoeol(std);
+
+        let segments = self.breadcrumb();
+        let last = segments.len().saturating_sub(1);
+        for (i, segment) in segments.iter().enumerate() {
+            let x = INFO_X + segment.x as i32;
+            let attr = match (segment.dirs.len() > 1, i == last) {
+                // El `…` de los que no caben
+                (true, _) => A_DIM(),
+                (false, true) => COLOR_PAIR(4) | A_BOLD(),
+                (false, false) => COLOR_PAIR(4),
+            };
+            wattron(std, attr);
+            mvwaddstr(std, INFO_Y, x, &segment.label);
+            wattroff(std, attr);
+            if segment.sep {
+                wattron(std, A_DIM());
+                mvwaddstr(std, INFO_Y, INFO_X + segment.end() as i32, "/");
+                wattroff(std, A_DIM());
+            }
+            if let Some(hint) = breadcrumb::hint(i).filter(|_| hints) {
+                wattron(std, A_REVERSE() | A_BOLD());
+                mvwaddstr(std, INFO_Y, x, &hint.to_string());
+                wattroff(std, A_REVERSE() | A_BOLD());
+            }
+        }
+
+        let (name, tags) = self.info_parts();
+        let rest = match name.is_empty() {
+            true => tags,
+            false => format!("  {}{}", name, tags),
+        };
+        let x = INFO_X + segments.last().map_or(0, |s| s.end()) as i32;
+        let width = (COLS() - 1 - x).max(0) as usize;
+        mvwaddstr(std, INFO_Y, x, &text::clip(&rest, width));
         wrefresh(std);
     }
 
+    // El nombre de lo seleccionado y los ajustes de la vista que no son los
+    // de siempre
+    fn info_parts(&self) -> (String, String) {
+        let name = self
+            .selected()
+            .and_then(|e| e.get_path().file_name())
+            .map(text::sanitize_os_str)
+            .unwrap_or_default();
+
+        let mut tags = String::new();
+        if let Some(filter) = &self.filter {
+            tags.push_str(&format!("  [filter: {}]", text::sanitize(filter)));
+        }
+        if let Some(depth) = self.flat {
+            tags.push_str(&format!("  [flat: {}]", depth));
+        }
+        if self.sort != Sort::Name {
+            tags.push_str(&format!("  [sort: {}]", self.sort.name()));
+        }
+        (name, tags)
+    }
+
+    /// La ruta de la cabecera partida en directorios. Se acorta por en medio
+    /// para que quepa con el resto de la cabecera.
+    pub fn breadcrumb(&self) -> Vec<Segment> {
+        let (name, tags) = self.info_parts();
+        let total = (COLS() - INFO_X - 1).max(0) as usize;
+        let reserved = text::width(&tags) + (text::width(&name) + 2).min(total / 3);
+        breadcrumb::segments(&self.path, total.saturating_sub(reserved))
+    }
+
+    /// El trozo de la ruta de la cabecera que hay en la columna `x` de la
+    /// pantalla.
+    pub fn breadcrumb_at(&self, x: i32) -> Option<Segment> {
+        let x = usize::try_from(x - INFO_X).ok()?;
+        breadcrumb::at(&self.breadcrumb(), x).cloned()
+    }
+
     pub fn change_dir<P: AsRef<Path>>(&mut self, path: P, is_folder: bool) {
         let path = path.as_ref().to_owned();
 
+        self.leave();
         self.path = path.clone();
+        self.marked.clear();
+        self.tree.clear();
+        self.top.set(0);
+        self.idx = 0;
+        self.want = None;
         match is_folder {
-            false => self.dir = MagEntry::File(MagFile::new_return(&path)),
-            true => self.dir = MagEntry::Dir(MagFolder::new(&path).get_entries_return().unwrap()),
+            false => {
+                self.loading = None;
+                self.dir = MagEntry::File(MagFile::new_return(&path, self.dimensions));
+            }
+            true => {
+                match cache::get(&path).filter(|_| self.flat.is_none()) {
+                    Some(mut folder) => {
+                        folder.arrange(self.sort, self.filter.as_deref(), self.hidden);
+                        self.loading = None;
+                        self.dir = MagEntry::Dir(folder);
+                    }
+                    None => {
+                        self.dir = MagEntry::Dir(MagFolder::new(&path));
+                        self.fetch();
+                    }
+                }
+                if let Some(last) = cache::cursor(&path) {
+                    self.select(last);
+                }
+            }
         }
     }
 
+    // Apunta donde estaba el cursor y guarda el listado que se deja para
+    // volver a el sin leerlo otra vez
+    fn leave(&mut self) {
+        if let Some(name) = self.selected().and_then(|e| e.get_path().file_name()) {
+            cache::remember_cursor(&self.path, name);
+        }
+        if self.loading.is_some() || !self.is_complete() {
+            return;
+        }
+        let dir = std::mem::replace(&mut self.dir, MagEntry::Dir(MagFolder::new("")));
+        if let MagEntry::Dir(folder) = dir {
+            if !folder.data.path.as_os_str().is_empty() {
+                cache::put(folder);
+            }
+        }
+    }
+
+    /// Pone el cursor sobre `path`. Si el directorio todavia se esta leyendo
+    /// se hara cuando llegue esa entrada.
+    pub fn select<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        match self.position(path) {
+            Some(idx) => {
+                self.idx = idx;
+                self.want = None;
+            }
+            None if self.loading.is_some() => self.want = Some(path.to_owned()),
+            None => self.want = None,
+        }
+    }
+
+    /// Deja la ventana sin nada, p.ej. la preview cuando no hay ninguna
+    /// entrada seleccionada.
+    pub fn clear(&mut self) {
+        self.leave();
+        self.path = PathBuf::new();
+        self.marked.clear();
+        self.tree.clear();
+        self.loading = None;
+        self.dir = MagEntry::Dir(MagFolder::new(""));
+    }
+
     pub fn fetch_return(self) -> Self {
         match self.dir {
             MagEntry::File(f) => {
                 let mut updated_file = MagFile::new(&f.data.path);
-                updated_file.file_contents();
+                updated_file.file_contents(self.dimensions);
                 Self {
                     dir: MagEntry::File(updated_file),
                     ..self
@@ -154,18 +481,408 @@ impl MagWindow {
         }
     }
 
+    /// Vuelve a leer el contenido. Los directorios se leen en segundo plano;
+    /// hay que llamar a `poll_load` para ir recibiendo las entradas.
     pub fn fetch(&mut self) {
-        match &self.dir {
-            MagEntry::File(f) => self.dir = MagEntry::File(MagFile::new(&f.data.path)),
+        match &mut self.dir {
+            MagEntry::File(f) => {
+                self.dir = MagEntry::File(MagFile::new_return(&f.data.path, self.dimensions))
+            }
             MagEntry::Dir(d) => {
-                self.dir = MagEntry::Dir(MagFolder::new(&d.data.path).get_entries_return().unwrap())
+                // La fecha de antes de leer es la que se guarda en la cache
+                d.data = MagItem::new(&d.data.path);
+                let fresh = match d.items.is_empty() {
+                    true => None,
+                    false => Some(Vec::new()),
+                };
+                let load = match self.flat {
+                    Some(depth) => loader::load_flat(&d.data.path, depth, self.hidden),
+                    None => loader::load(&d.data.path),
+                };
+                // Si habia otra lectura en marcha se cancela al soltarla
+                self.loading = Some(Loading { load, fresh });
+            }
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Recoge lo que haya leido el hilo de fondo. El cursor se queda en la
+    /// misma entrada aunque al ordenar cambie de posicion. Devuelve `true`
+    /// si hay que volver a pintar la ventana.
+    pub fn poll_load(&mut self) -> bool {
+        let expanded = self.poll_tree();
+        self.poll_listing() || expanded
+    }
+
+    // Las entradas de los directorios desplegados en el arbol
+    fn poll_tree(&mut self) -> bool {
+        let selected = self.selected().map(|e| e.get_path().clone());
+        let MagEntry::Dir(d) = &mut self.dir else {
+            return false;
+        };
+        if !self.tree.poll(d, self.sort, self.hidden) {
+            return false;
+        }
+        if let Some(path) = selected {
+            self.select(path);
+        }
+        true
+    }
+
+    fn poll_listing(&mut self) -> bool {
+        let Some(loading) = &mut self.loading else {
+            return false;
+        };
+        if !self.dir.is_folder() {
+            self.loading = None;
+            return false;
+        }
+
+        let msgs: Vec<Msg> = loading.load.try_iter().collect();
+        if msgs.is_empty() {
+            return false;
+        }
+
+        let want = self.want.take();
+        let selected = want
+            .clone()
+            .or_else(|| self.selected().map(|e| e.get_path().clone()));
+        let mut finished = false;
+        if let (MagEntry::Dir(folder), Some(loading)) = (&mut self.dir, &mut self.loading) {
+            for msg in msgs {
+                match msg {
+                    Msg::Entries(batch) => {
+                        let batch = batch.into_iter().map(MagEntry::from_entry);
+                        // Una recarga no se ve hasta el final, asi que se
+                        // ordena una sola vez; una carga nueva se va viendo y
+                        // cada tanda se mezcla con lo ya ordenado
+                        match &mut loading.fresh {
+                            Some(fresh) => fresh.extend(batch),
+                            None => folder.merge(
+                                batch.collect(),
+                                self.sort,
+                                self.filter.as_deref(),
+                                self.hidden,
+                            ),
+                        }
+                    }
+                    Msg::Done(result) => {
+                        folder.error = result.err().map(|e| filesys::io_error_message(&e));
+                        if let Some(fresh) = loading.fresh.take() {
+                            folder.items = fresh;
+                            folder.arrange(self.sort, self.filter.as_deref(), self.hidden);
+                        }
+                        finished = true;
+                    }
+                }
+            }
+            self.tree.rebuild(folder);
+        }
+        if finished {
+            self.loading = None;
+            if let MagEntry::Dir(folder) = &self.dir {
+                self.tree.refill(folder);
+            }
+        }
+
+        let found = selected.as_ref().and_then(|p| self.position(p));
+        self.idx = found
+            .unwrap_or(self.idx)
+            .min(self.count().saturating_sub(1));
+        // Lo que se buscaba todavia no ha llegado
+        if found.is_none() && !finished {
+            self.want = want;
+        }
+
+        if finished {
+            let rows = self.rows(0, usize::MAX);
+            let items: BTreeSet<PathBuf> =
+                rows.iter().map(|r| r.entry.get_path().clone()).collect();
+            self.marked.retain(|p| items.contains(p));
+            if let (true, MagEntry::Dir(folder)) = (self.is_complete(), &self.dir) {
+                cache::put(folder.clone());
+            }
+        }
+        true
+    }
+
+    // Solo los listados sin filtrar ni aplanar van a la cache
+    fn is_complete(&self) -> bool {
+        self.filter.is_none() && self.hidden && self.flat.is_none()
+    }
+
+    /// Cambia el orden, el filtro y si se ven los ocultos manteniendo el
+    /// cursor. Si lo nuevo deja ver entradas que se habian quitado se
+    /// vuelven a leer.
+    pub fn set_view(&mut self, sort: Sort, filter: Option<&str>, hidden: bool) {
+        if self.sort == sort && self.filter.as_deref() == filter && self.hidden == hidden {
+            return;
+        }
+        let widen = hidden && !self.hidden
+            || match (&self.filter, filter) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(old), Some(new)) => !new.to_lowercase().contains(&old.to_lowercase()),
+            };
+        // Los ocultos cambian lo que se recorre al aplanar
+        let walk_again = self.flat.is_some() && hidden != self.hidden;
+        self.sort = sort;
+        self.filter = filter.map(str::to_owned);
+        self.hidden = hidden;
+
+        let selected = self.selected().map(|e| e.get_path().clone());
+        let MagEntry::Dir(d) = &mut self.dir else {
+            return;
+        };
+        if walk_again {
+            self.fetch();
+        } else if widen {
+            match cache::get(&self.path).filter(|_| self.flat.is_none()) {
+                Some(folder) => d.items = folder.items,
+                None => self.fetch(),
             }
         }
+        if let MagEntry::Dir(d) = &mut self.dir {
+            d.arrange(sort, filter, hidden);
+            self.tree.refill(d);
+        }
+        self.idx = 0;
+        if let Some(path) = selected {
+            self.select(path);
+        }
+    }
+
+    /// Con `Some(depth)` la lista pasa a ser todos los ficheros que hay
+    /// debajo del directorio hasta esa profundidad.
+    pub fn set_flat(&mut self, flat: Option<usize>) {
+        if self.flat == flat || !self.dir.is_folder() {
+            return;
+        }
+        self.flat = flat;
+        self.tree.clear();
+        if let MagEntry::Dir(d) = &mut self.dir {
+            d.items.clear();
+        }
+        self.idx = 0;
+        self.top.set(0);
+        self.fetch();
+    }
+
+    /// Vuelve a leer el contenido despues de un cambio en el disco sin
+    /// perder la posicion: en un directorio el cursor sigue en la misma
+    /// entrada (o se queda en su sitio si ha desaparecido) y en un fichero
+    /// se mantiene el scroll.
+    pub fn reload(&mut self) {
+        match &self.dir {
+            MagEntry::Dir(_) => self.fetch(),
+            MagEntry::File(f) => {
+                let scroll = f.scroll;
+                self.fetch();
+                let rows = filesys::preview_rows(self.dimensions);
+                if let MagEntry::File(f) = &mut self.dir {
+                    f.load_lines(scroll + rows);
+                    f.scroll = scroll.min(f.lines.len().saturating_sub(rows));
+                }
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<&MagEntry> {
+        self.rows(self.idx, 1).pop().map(|r| r.entry)
+    }
+
+    /// Cuantas filas tiene la lista, contando las de los directorios
+    /// desplegados.
+    pub fn count(&self) -> usize {
+        match self.tree.is_empty() {
+            true => self.dir.get_folder().map_or(0, |d| d.items.len()),
+            false => self.tree.count(),
+        }
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        let d = self.dir.get_folder()?;
+        match self.tree.is_empty() {
+            true => d.items.iter().position(|e| e.get_path() == path),
+            false => self.tree.position(d, path),
+        }
+    }
+
+    // Las filas de `skip` a `skip + take`
+    fn rows(&self, skip: usize, take: usize) -> Vec<Row<'_>> {
+        let Some(d) = self.dir.get_folder() else {
+            return Vec::new();
+        };
+        if self.tree.is_empty() {
+            let flat = d.items.iter().skip(skip).take(take);
+            return flat.map(|entry| Row { entry, guide: "" }).collect();
+        }
+        self.tree
+            .rows(d, skip, take)
+            .map(|(entry, guide)| Row { entry, guide })
+            .collect()
+    }
+
+    /// Despliega en el arbol el directorio seleccionado; sus entradas se
+    /// leen en segundo plano. Devuelve `false` si lo seleccionado no es un
+    /// directorio.
+    pub fn expand(&mut self) -> bool {
+        let Some(path) = self
+            .selected()
+            .filter(|e| e.is_folder())
+            .map(|e| e.get_path().clone())
+        else {
+            return false;
+        };
+        if let MagEntry::Dir(d) = &self.dir {
+            self.tree.expand(d, path);
+        }
+        true
+    }
+
+    /// Pliega el directorio seleccionado o, si no esta desplegado, el que
+    /// lo contiene, dejando el cursor sobre el.
+    pub fn collapse(&mut self) {
+        let Some(selected) = self.selected().map(|e| e.get_path().clone()) else {
+            return;
+        };
+        let MagEntry::Dir(d) = &mut self.dir else {
+            return;
+        };
+        if let Some(path) = self.tree.collapse(d, &selected) {
+            self.select(path);
+        }
+    }
+
+    pub fn is_expanded(&self) -> bool {
+        self.selected()
+            .is_some_and(|e| self.tree.is_expanded(e.get_path()))
+    }
+
+    pub fn collapse_all(&mut self) {
+        if self.tree.is_empty() {
+            return;
+        }
+        let selected = self.selected().map(|e| e.get_path().clone());
+        let MagEntry::Dir(d) = &mut self.dir else {
+            return;
+        };
+        // El cursor pasa a la entrada de arriba del todo que lo contenia
+        let top = self.tree.collapse_all(d, selected.as_deref());
+        self.idx = 0;
+        if let Some(top) = top {
+            self.select(top);
+        }
+    }
+
+    /// Las entradas marcadas o, si no hay ninguna, la seleccionada.
+    pub fn selection(&self) -> Vec<PathBuf> {
+        if !self.marked.is_empty() {
+            return self.marked.iter().cloned().collect();
+        }
+        self.selected()
+            .map(|e| vec![e.get_path().to_owned()])
+            .unwrap_or_default()
+    }
+
+    pub fn toggle_mark(&mut self) {
+        let Some(path) = self.selected().map(|e| e.get_path().to_owned()) else {
+            return;
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+    }
+
+    /// Mueve la preview de un fichero `delta` lineas, leyendo mas del disco
+    /// si hace falta. No hace nada si la ventana muestra un directorio.
+    pub fn scroll_preview(&mut self, delta: isize) {
+        let rows = filesys::preview_rows(self.dimensions);
+        if let MagEntry::File(f) = &mut self.dir {
+            let target = f.scroll.saturating_add_signed(delta);
+            f.load_lines(target + rows);
+            let max = f.lines.len().saturating_sub(rows);
+            f.scroll = target.min(max);
+        }
+    }
+
+    pub fn scroll_preview_page(&mut self, pages: isize) {
+        let rows = filesys::preview_rows(self.dimensions) as isize;
+        self.scroll_preview(pages * rows);
+    }
+
+    pub fn scroll_preview_end(&mut self) {
+        let rows = filesys::preview_rows(self.dimensions);
+        if let MagEntry::File(f) = &mut self.dir {
+            f.load_all();
+            f.scroll = f.lines.len().saturating_sub(rows);
+        }
+    }
+
+    pub fn scroll_preview_start(&mut self) {
+        if let MagEntry::File(f) = &mut self.dir {
+            f.scroll = 0;
+        }
     }
 
     pub fn change_dim(&mut self, coord: Pos<i32>, dim: Pos<i32>) {
+        if coord == self.coord && dim == self.dimensions {
+            return;
+        }
         self.dimensions = dim;
         self.coord = coord;
+        // Se crea otra en vez de moverla: `mvwin` falla si al cambiar de
+        // tamaño la ventana se sale de la pantalla en algun momento
+        delwin(self.win);
         self.win = newwin(dim.y, dim.x, coord.y, coord.x);
     }
 }
+
+/// Fila de un listado: el nombre a la izquierda (acortado si no cabe) y el
+/// tamaño alineado a la derecha, ocupando exactamente `width` columnas. Con
+/// `with_time` se añade tambien la fecha de modificacion.
+fn entry_row(entry: &MagEntry, mut name: String, width: usize, with_time: bool) -> String {
+    let data = match entry {
+        MagEntry::File(f) => &f.data,
+        MagEntry::Dir(d) => &d.data,
+    };
+    if with_time {
+        if let Some(target) = data.link_target() {
+            name = format!("{} -> {}", name, text::sanitize_os_str(target.as_os_str()));
+        }
+    }
+
+    let size = match (entry, entry.metadata()) {
+        (MagEntry::File(_), Some(m)) => text::human_size(m.size),
+        _ => String::new(),
+    };
+    let mut meta = match with_time {
+        true => format!("{:>5}", size),
+        false => size,
+    };
+    if with_time {
+        let time = entry.metadata().and_then(|m| m.modified);
+        match time {
+            Some(t) => meta.push_str(&format!(" {}", text::format_time(t))),
+            None => meta.push_str(&" ".repeat(17)),
+        }
+    }
+
+    // Si la ventana es muy estrecha solo mostramos el nombre
+    let meta_width = text::width(&meta);
+    let name_width = if meta_width > 0 && width > meta_width + 8 {
+        width - meta_width - 1
+    } else {
+        width
+    };
+
+    let name = text::pad_right(&text::truncate_name(&name, name_width), name_width);
+    if name_width == width {
+        name
+    } else {
+        format!("{} {}", name, meta)
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fs::{self, File, Metadata},
+    io,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::SystemTime,
+};
+
+use crate::archive::{self, ArchiveFs};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+}
+
+/// Lo que necesitamos saber de una entrada, venga del disco o de dentro de
+/// un archivo comprimido.
+#[derive(Debug, Clone)]
+pub struct Stat {
+    pub kind: Kind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// La entrada es un enlace simbolico; el resto de campos son los de su
+    /// destino.
+    pub link: bool,
+}
+
+impl Stat {
+    pub fn is_file(&self) -> bool {
+        self.kind == Kind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == Kind::Dir
+    }
+}
+
+impl From<Metadata> for Stat {
+    fn from(m: Metadata) -> Self {
+        Self {
+            kind: if m.is_dir() { Kind::Dir } else { Kind::File },
+            size: m.len(),
+            modified: m.modified().ok(),
+            link: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub kind: Kind,
+    /// `None` si el sistema no lo da gratis al listar; se pide luego con
+    /// `stat` solo para las entradas que se llegan a mostrar.
+    pub stat: Option<Stat>,
+}
+
+impl DirEntry {
+    pub fn new(path: PathBuf, stat: Stat) -> Self {
+        Self {
+            path,
+            kind: stat.kind,
+            stat: Some(stat),
+        }
+    }
+}
+
+/// Fichero abierto para leer a trozos desde cualquier posicion.
+pub trait VfsFile: Send {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+    fn size(&self) -> io::Result<u64>;
+}
+
+impl VfsFile for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        FileExt::read_at(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// Contenido ya cargado en memoria, p.ej. un fichero descomprimido.
+pub struct MemFile(pub Arc<Vec<u8>>);
+
+impl VfsFile for MemFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let start = (offset as usize).min(self.0.len());
+        let n = buf.len().min(self.0.len() - start);
+        buf[..n].copy_from_slice(&self.0[start..start + n]);
+        Ok(n)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.0.len() as u64)
+    }
+}
+
+/// Un sistema de ficheros del que `MagFolder` y `MagFile` pueden leer. Las
+/// rutas que recibe son siempre las rutas completas que ve el usuario.
+///
+/// Ademas del disco (`LocalFs`) y los archivos comprimidos, que se detectan
+/// solos, se pueden montar otros con `mount`.
+pub trait Vfs: Send + Sync {
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Como `list`, pero pasa las entradas a `f` segun se van leyendo. Si
+    /// `f` devuelve `false` se deja de leer.
+    fn list_with(&self, path: &Path, f: &mut dyn FnMut(DirEntry) -> bool) -> io::Result<()> {
+        for entry in self.list(path)? {
+            if !f(entry) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat>;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>>;
+
+    fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+    }
+
+    /// Si es `false` mag no escribira nada en el (extraer, comprimir...).
+    fn is_writable(&self) -> bool {
+        false
+    }
+}
+
+pub struct LocalFs;
+
+impl Vfs for LocalFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        self.list_with(path, &mut |e| {
+            entries.push(e);
+            true
+        })?;
+        Ok(entries)
+    }
+
+    // El tipo viene con la propia entrada del directorio, sin hacer un stat
+    // por fichero. Solo los enlaces hay que seguirlos para saber si apuntan
+    // a un directorio.
+    fn list_with(&self, path: &Path, f: &mut dyn FnMut(DirEntry) -> bool) -> io::Result<()> {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+
+            let entry = if file_type.is_symlink() {
+                // Los enlaces rotos no se muestran
+                match self.stat(&path) {
+                    Ok(stat) => DirEntry::new(path, stat),
+                    Err(_) => continue,
+                }
+            } else {
+                let kind = match file_type.is_dir() {
+                    true => Kind::Dir,
+                    false => Kind::File,
+                };
+                DirEntry {
+                    path,
+                    kind,
+                    stat: None,
+                }
+            };
+
+            if !f(entry) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let mut stat: Stat = fs::metadata(path)?.into();
+        stat.link = fs::symlink_metadata(path)?.file_type().is_symlink();
+        Ok(stat)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+}
+
+type Mounts = Vec<(PathBuf, Arc<dyn Vfs>)>;
+
+fn mounts() -> &'static RwLock<Mounts> {
+    static MOUNTS: OnceLock<RwLock<Mounts>> = OnceLock::new();
+    MOUNTS.get_or_init(Default::default)
+}
+
+/// Hace que todo lo que hay por debajo de `prefix` se lea de `vfs`.
+pub fn mount<P: AsRef<Path>>(prefix: P, vfs: Arc<dyn Vfs>) {
+    let mut mounts = mounts().write().unwrap();
+    mounts.retain(|(p, _)| p != prefix.as_ref());
+    mounts.push((prefix.as_ref().to_owned(), vfs));
+    // El mas especifico primero
+    mounts.sort_by_key(|(p, _)| std::cmp::Reverse(p.components().count()));
+}
+
+pub fn unmount<P: AsRef<Path>>(prefix: P) {
+    mounts()
+        .write()
+        .unwrap()
+        .retain(|(p, _)| p != prefix.as_ref());
+}
+
+// Para `list` un archivo comprimido se comporta como un directorio; para el
+// resto de operaciones solo cuenta si esta por encima de `path`.
+fn resolve(path: &Path, list: bool) -> Arc<dyn Vfs> {
+    for (prefix, vfs) in mounts().read().unwrap().iter() {
+        if path.starts_with(prefix) {
+            return vfs.clone();
+        }
+    }
+
+    let mut ancestors = path.ancestors();
+    if !list {
+        ancestors.next();
+    }
+    for a in ancestors {
+        if archive::is_archive(a) && a.is_file() {
+            return Arc::new(ArchiveFs::new(a));
+        }
+    }
+
+    Arc::new(LocalFs)
+}
+
+pub fn list(path: &Path) -> io::Result<Vec<DirEntry>> {
+    resolve(path, true).list(path)
+}
+
+pub fn list_with(path: &Path, f: &mut dyn FnMut(DirEntry) -> bool) -> io::Result<()> {
+    resolve(path, true).list_with(path, f)
+}
+
+pub fn stat(path: &Path) -> io::Result<Stat> {
+    resolve(path, false).stat(path)
+}
+
+pub fn open(path: &Path) -> io::Result<Box<dyn VfsFile>> {
+    resolve(path, false).open(path)
+}
+
+pub fn read_link(path: &Path) -> io::Result<PathBuf> {
+    resolve(path, false).read_link(path)
+}
+
+/// `true` si las entradas de `path` no estan en el disco local (dentro de un
+/// archivo comprimido o de otro sistema montado) y no se puede escribir en
+/// ellas.
+pub fn is_virtual(path: &Path) -> bool {
+    !resolve(path, false).is_writable()
+}
+
+/// `true` si al entrar en `path` hay que listarlo como un directorio.
+pub fn is_browsable(path: &Path) -> bool {
+    match stat(path) {
+        Ok(s) if s.is_dir() => true,
+        Ok(_) => archive::is_archive(path) && !is_virtual(path),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,138 @@
+use std::{io, path::Path};
+
+use ignore::WalkBuilder;
+
+use crate::vfs::{self, DirEntry, Kind};
+
+/// Pasa a `f` todos los ficheros que hay debajo de `root` hasta `depth`
+/// niveles (1 son solo los de `root`). Se salta lo que diga `.gitignore`,
+/// el directorio `.git` y, si `hidden` es `false`, lo que empieza por punto.
+/// Si `f` devuelve `false` se deja de recorrer.
+///
+/// Dentro de un archivo comprimido no hay `.gitignore` que valga.
+pub fn walk(
+    root: &Path,
+    depth: usize,
+    hidden: bool,
+    f: &mut dyn FnMut(DirEntry) -> bool,
+) -> io::Result<()> {
+    if vfs::is_virtual(root) {
+        walk_vfs(root, depth, hidden, f).map(|_| ())
+    } else {
+        walk_disk(root, depth, hidden, f)
+    }
+}
+
+fn walk_disk(
+    root: &Path,
+    depth: usize,
+    hidden: bool,
+    f: &mut dyn FnMut(DirEntry) -> bool,
+) -> io::Result<()> {
+    // Que falle igual que al listar si no se puede leer
+    std::fs::read_dir(root)?;
+
+    let walker = WalkBuilder::new(root)
+        .max_depth(Some(depth))
+        .hidden(!hidden)
+        .require_git(false)
+        .filter_entry(|e| e.file_name() != ".git")
+        .build();
+    // Lo que no se puede leer por el camino simplemente no sale. Los
+    // enlaces a directorios no se siguen, pero tampoco son ficheros
+    for entry in walker.flatten() {
+        if entry.file_type().is_some_and(|t| t.is_dir()) || entry.path().is_dir() {
+            continue;
+        }
+        let entry = DirEntry {
+            path: entry.into_path(),
+            kind: Kind::File,
+            stat: None,
+        };
+        if !f(entry) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Devuelve `false` si `f` ha pedido parar
+fn walk_vfs(
+    dir: &Path,
+    depth: usize,
+    hidden: bool,
+    f: &mut dyn FnMut(DirEntry) -> bool,
+) -> io::Result<bool> {
+    if depth == 0 {
+        return Ok(true);
+    }
+    for entry in vfs::list(dir)? {
+        let name = entry.path.file_name().unwrap_or_default();
+        if !hidden && name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let keep_going = match entry.kind {
+            Kind::Dir => walk_vfs(&entry.path, depth - 1, hidden, f).unwrap_or(true),
+            Kind::File => f(entry),
+        };
+        if !keep_going {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn walk_files() {
+        let dir = std::env::temp_dir().join(format!("mag-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src/deep/er")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        for f in [
+            "a.txt",
+            ".env",
+            ".git/HEAD",
+            "target/bin",
+            "src/main.rs",
+            "src/deep/er/x",
+        ] {
+            fs::write(dir.join(f), "").unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        std::os::unix::fs::symlink("src", dir.join("enlace")).unwrap();
+        std::os::unix::fs::symlink("a.txt", dir.join("b.txt")).unwrap();
+
+        let found = |depth, hidden| {
+            let mut paths = Vec::new();
+            walk(&dir, depth, hidden, &mut |e| {
+                paths.push(e.path.strip_prefix(&dir).unwrap().to_owned());
+                true
+            })
+            .unwrap();
+            paths.sort();
+            paths
+        };
+        let paths = |p: &[&str]| -> Vec<PathBuf> { p.iter().map(PathBuf::from).collect() };
+
+        assert_eq!(found(2, false), paths(&["a.txt", "b.txt", "src/main.rs"]));
+        assert_eq!(
+            found(9, true),
+            paths(&[
+                ".env",
+                ".gitignore",
+                "a.txt",
+                "b.txt",
+                "src/deep/er/x",
+                "src/main.rs"
+            ])
+        );
+        assert!(walk(&dir.join("nope"), 2, true, &mut |_| true).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+// Tiempo sin eventos nuevos antes de avisar. Asi una descompresion o un
+// `git checkout` producen un solo refresco en vez de miles.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// Pero si no paran de llegar, se avisa igual pasado este tiempo desde el
+// primero, para que un log que se escribe sin parar tambien se refresque.
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+
+/// Vigila con inotify los directorios visibles. No usa hilos: el bucle
+/// principal llama a `poll` en cada vuelta.
+pub struct Watcher {
+    inotify: Inotify,
+    watches: HashMap<WatchDescriptor, PathBuf>,
+    targets: Vec<PathBuf>,
+    changed: BTreeSet<PathBuf>,
+    debounce: Debounce,
+    buffer: Vec<u8>,
+}
+
+// Cuando avisar de los eventos pendientes. Las horas se pasan desde fuera
+// para poder probarlo sin esperar.
+#[derive(Debug, Default)]
+struct Debounce {
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Debounce {
+    fn event(&mut self, now: Instant) {
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+    }
+
+    // `true` si toca avisar; entonces se empieza de nuevo
+    fn ready(&mut self, now: Instant) -> bool {
+        let quiet = self.last.is_some_and(|t| now - t >= DEBOUNCE);
+        let overdue = self.first.is_some_and(|t| now - t >= MAX_LATENCY);
+        if quiet || overdue {
+            *self = Self::default();
+        }
+        quiet || overdue
+    }
+}
+
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init()?,
+            watches: HashMap::new(),
+            targets: Vec::new(),
+            changed: BTreeSet::new(),
+            debounce: Debounce::default(),
+            buffer: vec![0; 4096],
+        })
+    }
+
+    /// Cambia los directorios vigilados. Si son los mismos que ya habia no
+    /// hace nada, asi que se puede llamar despues de cada movimiento.
+    pub fn watch(&mut self, dirs: &[&Path]) {
+        if self
+            .targets
+            .iter()
+            .map(PathBuf::as_path)
+            .eq(dirs.iter().copied())
+        {
+            return;
+        }
+
+        let mut watches = self.inotify.watches();
+        for (wd, _) in self.watches.drain() {
+            let _ = watches.remove(wd);
+        }
+
+        let mask = WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MODIFY
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::ATTRIB
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::DELETE_SELF
+            | WatchMask::MOVE_SELF;
+        for dir in dirs {
+            // Falla con las rutas dentro de un archivo comprimido; esas no
+            // pueden cambiar mientras el archivo no cambie.
+            if let Ok(wd) = watches.add(dir, mask) {
+                self.watches.insert(wd, dir.to_path_buf());
+            }
+        }
+
+        self.targets = dirs.iter().map(|d| d.to_path_buf()).collect();
+    }
+
+    /// Rutas que han cambiado: el directorio vigilado y, si el evento es
+    /// sobre una entrada concreta, tambien la ruta de esa entrada. Devuelve
+    /// un conjunto vacio mientras sigan llegando eventos, salvo que el
+    /// primero pendiente sea de hace mas de `MAX_LATENCY`.
+    pub fn poll(&mut self) -> BTreeSet<PathBuf> {
+        while let Ok(events) = self.inotify.read_events(&mut self.buffer) {
+            let mut any = false;
+            for event in events {
+                let Some(dir) = self.watches.get(&event.wd) else {
+                    continue;
+                };
+                self.changed.insert(dir.clone());
+                if let Some(name) = event.name {
+                    self.changed.insert(dir.join(name));
+                }
+                any = true;
+            }
+            if !any {
+                break;
+            }
+            self.debounce.event(Instant::now());
+        }
+
+        match self.debounce.ready(Instant::now()) {
+            true => std::mem::take(&mut self.changed),
+            false => BTreeSet::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher")
+            .field("targets", &self.targets)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread::sleep};
+
+    #[test]
+    fn debounce() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let mut d = Debounce::default();
+        assert!(!d.ready(t0));
+
+        // Se espera a que dejen de llegar
+        d.event(ms(0));
+        d.event(ms(100));
+        assert!(!d.ready(ms(200)));
+        assert!(d.ready(ms(250)));
+        assert!(!d.ready(ms(1000)));
+
+        // Pero no mas de `MAX_LATENCY` desde el primero
+        for n in (2000..3100).step_by(100) {
+            d.event(ms(n));
+            assert_eq!(d.ready(ms(n)), n >= 3000);
+        }
+    }
+
+    // Que lleguen de verdad los eventos de inotify
+    #[test]
+    fn inotify_events() {
+        let dir = std::env::temp_dir().join(format!("mag-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let wait = |watcher: &mut Watcher| {
+            for _ in 0..100 {
+                let changed = watcher.poll();
+                if !changed.is_empty() {
+                    return changed;
+                }
+                sleep(DEBOUNCE / 3);
+            }
+            BTreeSet::new()
+        };
+
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch(&[&dir]);
+        fs::write(dir.join("a"), "1").unwrap();
+        fs::write(dir.join("b"), "2").unwrap();
+        let changed = wait(&mut watcher);
+        assert!(changed.contains(&dir));
+        assert!(changed.contains(&dir.join("a")));
+        assert!(changed.contains(&dir.join("b")));
+
+        // Al cambiar de directorio se deja de vigilar el anterior
+        let other = dir.join("sub");
+        fs::create_dir(&other).unwrap();
+        assert!(wait(&mut watcher).contains(&other));
+        watcher.watch(&[&other]);
+        fs::write(dir.join("c"), "3").unwrap();
+        fs::write(other.join("d"), "4").unwrap();
+        assert!(!wait(&mut watcher).contains(&dir.join("c")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}